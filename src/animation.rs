@@ -0,0 +1,191 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Scene;
+use definitions::lerp;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Interpolation{
+  Linear,
+  Bezier,
+}
+
+//What a track animates, objects are referenced by the order they appear in the script.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Target{
+  Camera,
+  Light(usize),
+  Sphere(usize),
+  Mesh(usize),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Property{
+  Position,
+  LookAt,
+  Fov,
+  Intensity,
+  Radius,
+}
+
+impl Property{
+
+  pub fn from_name(name: &str) -> Option<Property> {
+    match name {
+      "position" => Some(Property::Position),
+      "target" => Some(Property::LookAt),
+      "fov" => Some(Property::Fov),
+      "intensity" => Some(Property::Intensity),
+      "radius" => Some(Property::Radius),
+      _ => None,
+    }
+  }
+
+  //How many numbers a keyframe of this property holds.
+  pub fn size(&self) -> usize {
+    match self {
+      Property::Position | Property::LookAt => 3,
+      _ => 1,
+    }
+  }
+
+}
+
+//A single key, scalar properties only use the x component of the value.
+#[derive(Debug, Copy, Clone)]
+pub struct Keyframe{
+  pub frame: f32,
+  pub value: Vector3,
+  pub interpolation: Interpolation,
+}
+
+#[derive(Debug, Clone)]
+pub struct Track{
+  pub target: Target,
+  pub property: Property,
+  pub keys: Vec<Keyframe>,
+}
+
+impl Track{
+
+  pub fn new(target: Target, property: Property) -> Self {
+    Track {target, property, keys: Vec::new()}
+  }
+
+  //Keeps the keys sorted by frame, a key on an existing frame replaces it.
+  pub fn insert(&mut self, key: Keyframe) {
+    match self.keys.iter().position(|k| k.frame >= key.frame) {
+      Some(i) if self.keys[i].frame == key.frame => self.keys[i] = key,
+      Some(i) => self.keys.insert(i, key),
+      None => self.keys.push(key),
+    }
+  }
+
+  //Slope at a key, used to place the automatic Bezier handles (flat at the ends).
+  fn slope(&self, i: usize) -> Vector3 {
+    if i == 0 || i + 1 >= self.keys.len() {
+      return Vector3::new(0.0, 0.0, 0.0);
+    }
+    let prev = self.keys[i - 1];
+    let next = self.keys[i + 1];
+    return (next.value - prev.value)*(1.0/(next.frame - prev.frame));
+  }
+
+  //Value of the track at a (possibly fractional) frame, held constant outside the keyed range.
+  pub fn evaluate(&self, frame: f32) -> Vector3 {
+    let first = self.keys[0];
+    let last = self.keys[self.keys.len() - 1];
+    if frame <= first.frame {
+      return first.value;
+    }
+    if frame >= last.frame {
+      return last.value;
+    }
+    let i = self.keys.iter().position(|k| k.frame > frame).unwrap() - 1;
+    let k0 = self.keys[i];
+    let k1 = self.keys[i + 1];
+    let span = k1.frame - k0.frame;
+    let t = (frame - k0.frame)/span;
+    match k0.interpolation {
+      Interpolation::Linear => lerp(k0.value, k1.value, t),
+      Interpolation::Bezier => {
+        //Handles sit a third of the way along the segment, so the curve is uniform in time.
+        let c0 = k0.value + self.slope(i)*(span/3.0);
+        let c1 = k1.value - self.slope(i + 1)*(span/3.0);
+        let u = 1.0 - t;
+        k0.value*(u*u*u) + c0*(3.0*u*u*t) + c1*(3.0*u*t*t) + k1.value*(t*t*t)
+      },
+    }
+  }
+
+}
+
+#[derive(Debug, Clone)]
+pub struct Animation{
+  pub tracks: Vec<Track>,
+}
+
+impl Animation{
+
+  pub fn new() -> Self {
+    Animation {tracks: Vec::new()}
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.tracks.is_empty()
+  }
+
+  //Adds a key, creating the track for this target and property if needed.
+  pub fn add_key(&mut self, target: Target, property: Property, key: Keyframe) {
+    let index = match self.tracks.iter().position(|t| t.target == target && t.property == property) {
+      Some(index) => index,
+      None => {
+        self.tracks.push(Track::new(target, property));
+        self.tracks.len() - 1
+      }
+    };
+    self.tracks[index].insert(key);
+  }
+
+  //Drops tracks that point at objects which don't exist or properties they don't have.
+  pub fn validate(&mut self, scene: &Scene) {
+    self.tracks.retain(|track| {
+      let valid = match (track.target, track.property) {
+        (Target::Camera, Property::Position) | (Target::Camera, Property::LookAt) | (Target::Camera, Property::Fov) => true,
+        (Target::Light(i), Property::Position) | (Target::Light(i), Property::Intensity) => i < scene.lights.len(),
+        (Target::Sphere(i), Property::Position) | (Target::Sphere(i), Property::Radius) => i < scene.spheres.len(),
+        (Target::Mesh(i), Property::Position) => i < scene.meshes.len(),
+        _ => false,
+      };
+      if !valid {
+        eprintln!("Ignoring keyframes for {:?} {:?}, there is no such object or property.", track.target, track.property);
+      }
+      valid
+    });
+  }
+
+  //Poses the scene for a frame, moving objects get their end position from the next frame for motion blur.
+  pub fn apply(&self, scene: &mut Scene, frame: f32) {
+    for track in &self.tracks {
+      let value = track.evaluate(frame);
+      let next = track.evaluate(frame + 1.0);
+      match (track.target, track.property) {
+        (Target::Camera, Property::Position) => scene.camera.transform = value,
+        (Target::Camera, Property::LookAt) => scene.camera.target = value,
+        (Target::Camera, Property::Fov) => scene.camera.fov = value.x,
+        (Target::Light(i), Property::Position) => scene.lights[i].transform = value,
+        (Target::Light(i), Property::Intensity) => scene.lights[i].intensity = value.x,
+        (Target::Sphere(i), Property::Position) => {
+          scene.spheres[i].transform = value;
+          scene.spheres[i].transform_end = next;
+        },
+        (Target::Sphere(i), Property::Radius) => scene.spheres[i].radius = value.x,
+        (Target::Mesh(i), Property::Position) => {
+          scene.meshes[i].transform = value;
+          scene.meshes[i].transform_end = next;
+        },
+        _ => (),
+      }
+    }
+  }
+
+}
@@ -0,0 +1,159 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Ray;
+
+//How many primitives a leaf holds before it gets split.
+const LEAF_SIZE: usize = 4;
+
+//An axis aligned bounding box.
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb{
+  pub min: Vector3,
+  pub max: Vector3,
+}
+
+impl Aabb{
+
+  pub fn empty() -> Self {
+    Aabb {min: Vector3::new(f32::MAX, f32::MAX, f32::MAX), max: Vector3::new(f32::MIN, f32::MIN, f32::MIN)}
+  }
+
+  pub fn grow(&mut self, p: Vector3) {
+    self.min = Vector3::new(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z));
+    self.max = Vector3::new(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z));
+  }
+
+  pub fn merge(&mut self, other: &Aabb) {
+    self.grow(other.min);
+    self.grow(other.max);
+  }
+
+  pub fn center(&self) -> Vector3 {
+    (self.min + self.max)*0.5
+  }
+
+  pub fn corners(&self) -> [Vector3; 8] {
+    let (a, b) = (self.min, self.max);
+    [
+      Vector3::new(a.x, a.y, a.z), Vector3::new(b.x, a.y, a.z), Vector3::new(a.x, b.y, a.z), Vector3::new(b.x, b.y, a.z),
+      Vector3::new(a.x, a.y, b.z), Vector3::new(b.x, a.y, b.z), Vector3::new(a.x, b.y, b.z), Vector3::new(b.x, b.y, b.z),
+    ]
+  }
+
+  //Where the ray enters the box, if it does before t_max.
+  pub fn hit(&self, origin: Vector3, inverse_direction: Vector3, t_max: f32) -> Option<f32> {
+    let tx1 = (self.min.x - origin.x)*inverse_direction.x;
+    let tx2 = (self.max.x - origin.x)*inverse_direction.x;
+    let ty1 = (self.min.y - origin.y)*inverse_direction.y;
+    let ty2 = (self.max.y - origin.y)*inverse_direction.y;
+    let tz1 = (self.min.z - origin.z)*inverse_direction.z;
+    let tz2 = (self.max.z - origin.z)*inverse_direction.z;
+    let near = tx1.min(tx2).max(ty1.min(ty2)).max(tz1.min(tz2));
+    let far = tx1.max(tx2).min(ty1.max(ty2)).min(tz1.max(tz2));
+    if far >= near.max(0.0) && near <= t_max {
+      return Some(near);
+    }
+    None
+  }
+
+}
+
+//Interior nodes have a count of 0 and their children at first and first + 1, leaves hold count primitives from first.
+#[derive(Debug, Copy, Clone)]
+pub struct BvhNode{
+  pub bounds: Aabb,
+  pub first: usize,
+  pub count: usize,
+}
+
+//A bounding volume hierarchy over anything with a bounding box (triangles of a mesh, instances in the scene).
+#[derive(Debug, Clone)]
+pub struct Bvh{
+  pub nodes: Vec<BvhNode>,
+  pub indices: Vec<usize>,
+}
+
+impl Bvh{
+
+  //Splits at the median of the centers along the longest axis, which is quick and good enough for our scenes.
+  pub fn build(bounds: &Vec<Aabb>) -> Self {
+    let mut bvh = Bvh {nodes: Vec::new(), indices: (0..bounds.len()).collect()};
+    if bounds.is_empty() {
+      return bvh;
+    }
+    bvh.nodes.push(BvhNode {bounds: Aabb::empty(), first: 0, count: bounds.len()});
+    let mut stack: Vec<usize> = vec![0];
+    while let Some(n) = stack.pop() {
+      let (first, count) = (bvh.nodes[n].first, bvh.nodes[n].count);
+      let mut node_bounds = Aabb::empty();
+      let mut centers = Aabb::empty();
+      for &i in &bvh.indices[first..first + count] {
+        node_bounds.merge(&bounds[i]);
+        centers.grow(bounds[i].center());
+      }
+      bvh.nodes[n].bounds = node_bounds;
+      if count <= LEAF_SIZE {
+        continue;
+      }
+      let extent = centers.max - centers.min;
+      let axis = if extent.x >= extent.y && extent.x >= extent.z {0} else if extent.y >= extent.z {1} else {2};
+      let key = |i: &usize| {
+        let c = bounds[*i].center();
+        match axis {0 => c.x, 1 => c.y, _ => c.z}
+      };
+      bvh.indices[first..first + count].sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap_or(std::cmp::Ordering::Equal));
+      let half = count/2;
+      let left = bvh.nodes.len();
+      bvh.nodes.push(BvhNode {bounds: Aabb::empty(), first, count: half});
+      bvh.nodes.push(BvhNode {bounds: Aabb::empty(), first: first + half, count: count - half});
+      bvh.nodes[n].first = left;
+      bvh.nodes[n].count = 0;
+      stack.push(left);
+      stack.push(left + 1);
+    }
+    return bvh;
+  }
+
+  //Calls test for every primitive whose boxes the ray goes through, nearest boxes first.
+  //test returns the distance of a hit, anything farther than the closest hit so far is skipped.
+  pub fn traverse<F: FnMut(usize) -> Option<f32>>(&self, ray: Ray, mut t_max: f32, mut test: F) {
+    if self.nodes.is_empty() {
+      return;
+    }
+    let inverse = Vector3::new(1.0/ray.direction.x, 1.0/ray.direction.y, 1.0/ray.direction.z);
+    if self.nodes[0].bounds.hit(ray.origin, inverse, t_max).is_none() {
+      return;
+    }
+    let mut stack: Vec<usize> = vec![0];
+    while let Some(n) = stack.pop() {
+      let node = self.nodes[n];
+      if node.count > 0 {
+        for &i in &self.indices[node.first..node.first + node.count] {
+          if let Some(t) = test(i) {
+            t_max = t_max.min(t);
+          }
+        }
+        continue;
+      }
+      let left = self.nodes[node.first].bounds.hit(ray.origin, inverse, t_max);
+      let right = self.nodes[node.first + 1].bounds.hit(ray.origin, inverse, t_max);
+      match (left, right) {
+        (Some(l), Some(r)) => {
+          //The far child goes on the stack first so the near one is looked at next.
+          if l <= r {
+            stack.push(node.first + 1);
+            stack.push(node.first);
+          }
+          else {
+            stack.push(node.first);
+            stack.push(node.first + 1);
+          }
+        },
+        (Some(_), None) => stack.push(node.first),
+        (None, Some(_)) => stack.push(node.first + 1),
+        (None, None) => (),
+      }
+    }
+  }
+
+}
@@ -0,0 +1,154 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Vector2;
+use definitions::Settings;
+
+//Import the sample accumulation from film.rs
+use film::Film;
+use film::PixelSamples;
+
+//Import the render passes from passes.rs
+use passes::Aov;
+use passes::AovAccumulator;
+
+//Import all of the standard libraries we need.
+use std::fs::{self, File};
+use std::io::{self, Read, Write, BufReader, BufWriter};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 2;
+
+//Hashes the script so a checkpoint is only resumed with the scene it was rendered from.
+//The files it loads go in by name, size and modification time, reading every mesh again just for this would be slow.
+pub fn scene_hash(script: &str, assets: &Vec<String>) -> u64{
+  let mut bytes = script.as_bytes().to_vec();
+  for asset in assets {
+    bytes.extend_from_slice(asset.as_bytes());
+    if let Ok(metadata) = fs::metadata(asset) {
+      bytes.extend_from_slice(&metadata.len().to_le_bytes());
+      if let Ok(since) = metadata.modified().map(|m| m.duration_since(UNIX_EPOCH)) {
+        bytes.extend_from_slice(&since.map_or(0, |d| d.as_nanos()).to_le_bytes());
+      }
+    }
+  }
+  return hash_bytes(&bytes);
+}
+
+//FNV-1a, quick and good enough to notice a file has changed.
+pub fn hash_bytes(bytes: &[u8]) -> u64{
+  let mut h: u64 = 0xcbf29ce484222325;
+  for &b in bytes {
+    h ^= b as u64;
+    h = h.wrapping_mul(0x100000001b3);
+  }
+  return h;
+}
+
+//Saves everything the film has accumulated for a frame, the sample counts are what the random numbers continue from.
+//The file is written next to the real one and renamed over it, so being killed halfway never leaves a broken checkpoint.
+pub fn write_checkpoint(filename: &str, film: &Film, hash: u64, settings: &Settings, frame: i32) -> io::Result<()>{
+  let temporary = format!("{}.tmp", filename);
+  {
+    let mut file = BufWriter::new(File::create(&temporary)?);
+    file.write_all(MAGIC)?;
+    file.write_all(&VERSION.to_le_bytes())?;
+    file.write_all(&hash.to_le_bytes())?;
+    file.write_all(&settings.seed.to_le_bytes())?;
+    file.write_all(&frame.to_le_bytes())?;
+    file.write_all(&(settings.fwidth as u32).to_le_bytes())?;
+    file.write_all(&(settings.fheight as u32).to_le_bytes())?;
+    for pixel in &film.pixels {
+      let aov = &pixel.aov;
+      let floats = [
+        pixel.color.x, pixel.color.y, pixel.color.z, pixel.luminance, pixel.luminance_squared,
+        aov.sum.depth, aov.sum.normal.x, aov.sum.normal.y, aov.sum.normal.z,
+        aov.sum.albedo.x, aov.sum.albedo.y, aov.sum.albedo.z,
+        aov.sum.direct.x, aov.sum.direct.y, aov.sum.direct.z,
+        aov.sum.indirect.x, aov.sum.indirect.y, aov.sum.indirect.z,
+        aov.sum.shadow, aov.count, aov.hits, aov.sum.uv.x, aov.sum.uv.y,
+      ];
+      for f in floats.iter() {
+        file.write_all(&f.to_le_bytes())?;
+      }
+      file.write_all(&pixel.count.to_le_bytes())?;
+      for v in aov.objects.iter().chain(aov.materials.iter()) {
+        file.write_all(&v.0.to_le_bytes())?;
+        file.write_all(&v.1.to_le_bytes())?;
+      }
+    }
+    file.flush()?;
+  }
+  fs::rename(&temporary, filename)
+}
+
+//Loads a checkpoint and returns the frame it belongs to with its film, refusing anything that doesn't match the current script.
+pub fn read_checkpoint(filename: &str, hash: u64, settings: &Settings) -> io::Result<(i32, Film)>{
+  let mut file = BufReader::new(File::open(filename)?);
+  let mut magic = [0u8; 4];
+  file.read_exact(&mut magic)?;
+  if &magic != MAGIC || read_u32(&mut file)? != VERSION {
+    return Err(invalid("not a checkpoint written by this version of the renderer"));
+  }
+  if read_u64(&mut file)? != hash {
+    return Err(invalid("the script or a file it loads has changed since the checkpoint was written"));
+  }
+  if read_u32(&mut file)? != settings.seed {
+    return Err(invalid("the checkpoint was rendered with a different seed"));
+  }
+  let frame = read_u32(&mut file)? as i32;
+  if read_u32(&mut file)? as usize != settings.fwidth || read_u32(&mut file)? as usize != settings.fheight {
+    return Err(invalid("the checkpoint has a different resolution"));
+  }
+  if frame < settings.frame_start || frame > settings.frame_end {
+    return Err(invalid("the checkpoint's frame is outside the frame range"));
+  }
+
+  let mut film = Film::new(settings.fwidth, settings.fheight);
+  for pixel in film.pixels.iter_mut() {
+    let mut f = [0.0f32; 23];
+    for v in f.iter_mut() {
+      *v = f32::from_bits(read_u32(&mut file)?);
+    }
+    let count = read_u32(&mut file)?;
+    let mut votes = [(0u32, 0u32); 8];
+    for v in votes.iter_mut() {
+      *v = (read_u32(&mut file)?, read_u32(&mut file)?);
+    }
+    let mut aov = AovAccumulator::new();
+    aov.sum = Aov{
+      depth: f[5],
+      normal: Vector3::new(f[6], f[7], f[8]),
+      uv: Vector2::new(f[21], f[22]),
+      albedo: Vector3::new(f[9], f[10], f[11]),
+      direct: Vector3::new(f[12], f[13], f[14]),
+      indirect: Vector3::new(f[15], f[16], f[17]),
+      shadow: f[18],
+      object_id: 0,
+      material_id: 0,
+      samples: 0,
+    };
+    aov.count = f[19];
+    aov.hits = f[20];
+    aov.objects.copy_from_slice(&votes[..4]);
+    aov.materials.copy_from_slice(&votes[4..]);
+    *pixel = PixelSamples {color: Vector3::new(f[0], f[1], f[2]), luminance: f[3], luminance_squared: f[4], count, aov};
+  }
+  Ok((frame, film))
+}
+
+pub fn read_u32(file: &mut dyn Read) -> io::Result<u32>{
+  let mut bytes = [0u8; 4];
+  file.read_exact(&mut bytes)?;
+  Ok(u32::from_le_bytes(bytes))
+}
+
+pub fn read_u64(file: &mut dyn Read) -> io::Result<u64>{
+  let mut bytes = [0u8; 8];
+  file.read_exact(&mut bytes)?;
+  Ok(u64::from_le_bytes(bytes))
+}
+
+pub fn invalid(message: &str) -> io::Error{
+  io::Error::new(io::ErrorKind::InvalidData, message)
+}
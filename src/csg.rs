@@ -0,0 +1,220 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Vector2;
+use definitions::Vector4;
+use definitions::Material;
+use definitions::Shape;
+use definitions::Ray;
+
+//Import the intersection of a single primitive from intersect.rs
+use intersect::shape_intersect;
+
+//Import the normal and bump maps from texture.rs
+use texture::detail_normal;
+
+//How many surfaces of one primitive we follow a ray through, meshes can have a lot of them.
+const MAX_CROSSINGS: usize = 64;
+//How far past a surface the next search starts.
+const STEP: f32 = 0.0001;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CsgOperation{
+  Union,
+  Intersection,
+  Difference,
+}
+
+impl CsgOperation{
+
+  pub fn from_name(name: &str) -> Option<CsgOperation> {
+    match name {
+      "union" => Some(CsgOperation::Union),
+      "intersection" => Some(CsgOperation::Intersection),
+      "difference" => Some(CsgOperation::Difference),
+      _ => None,
+    }
+  }
+
+  fn inside(&self, a: bool, b: bool) -> bool {
+    match self {
+      CsgOperation::Union => a || b,
+      CsgOperation::Intersection => a && b,
+      CsgOperation::Difference => a && !b,
+    }
+  }
+
+}
+
+//A tree of primitives, the difference takes everything after the first child away from it.
+#[derive(Debug, Clone)]
+pub enum CsgNode{
+  Leaf(Shape),
+  Operation(CsgOperation, Vec<CsgNode>),
+}
+
+//One CSG object in the scene, every surface of it shares the object ID.
+#[derive(Debug, Clone)]
+pub struct Csg{
+  pub root: CsgNode,
+  pub id: u32,
+}
+
+impl Csg{
+
+  pub fn new(root: CsgNode, id: u32) -> Self {
+    Csg {root, id}
+  }
+
+}
+
+//Where a ray goes through a surface, the normal always points out of the solid.
+//Mesh normals already have their maps applied (detailed), the maps of other primitives are applied once the crossing is picked.
+#[derive(Debug, Clone)]
+struct Crossing{
+  t: f32,
+  normal: Vector3,
+  uv: Vector2,
+  tangent: Option<Vector4>,
+  material: Material,
+  detailed: bool,
+}
+
+impl Crossing{
+
+  //Turns the surface around, the bitangent flips with the normal so v still runs the same way.
+  fn flip(&mut self){
+    self.normal = self.normal*-1.0;
+    if let Some(tangent) = self.tangent.as_mut(){
+      tangent.a = -tangent.a;
+    }
+  }
+
+}
+
+//A stretch of the ray that is inside the solid, a ray starting inside begins at minus infinity.
+#[derive(Debug, Clone)]
+struct Span{
+  enter: Crossing,
+  exit: Crossing,
+}
+
+//Finds the first surface of the CSG object in front of the ray.
+pub fn csg_intersect(csg: &Csg, ray: Ray) -> Option<(f32, Vector3, Vector2, Option<Vector4>, Material)>{
+  for span in spans(&csg.root, ray){
+    for crossing in vec![span.enter, span.exit]{
+      if crossing.t > STEP && crossing.t.is_finite(){
+        let normal = if crossing.detailed {crossing.normal} else {detail_normal(&crossing.material, crossing.normal, crossing.tangent, crossing.uv)};
+        return Some((crossing.t, normal, crossing.uv, crossing.tangent, crossing.material));
+      }
+    }
+  }
+  return None;
+}
+
+fn spans(node: &CsgNode, ray: Ray) -> Vec<Span>{
+  match node{
+    CsgNode::Leaf(shape) => leaf_spans(shape, ray),
+    CsgNode::Operation(operation, children) => {
+      let mut result = match children.first(){
+        Some(first) => spans(first, ray),
+        None => return Vec::new(),
+      };
+      for child in &children[1..]{
+        result = combine(result, spans(child, ray), *operation);
+      }
+      result
+    }
+  }
+}
+
+//Follows the ray through every surface of a primitive, whether a crossing goes in or out comes from its normal.
+//Only closed primitives make sense here (and planes, which count as everything behind them).
+fn leaf_spans(shape: &Shape, ray: Ray) -> Vec<Span>{
+  let mut crossings: Vec<Crossing> = Vec::new();
+  let mut travelled = 0.0;
+  let detailed = match shape{
+    Shape::Mesh(_) => true,
+    _ => false,
+  };
+  while crossings.len() < MAX_CROSSINGS{
+    let origin = ray.origin + ray.direction*travelled;
+    match shape_intersect(shape, Ray::new(origin, ray.direction, ray.time)){
+      Some((t, normal, uv, tangent, material)) => {
+        crossings.push(Crossing {t: travelled + t, normal, uv, tangent, material, detailed});
+        travelled += t + STEP;
+      }
+      None => break,
+    }
+  }
+
+  let mut spans: Vec<Span> = Vec::new();
+  let mut enter: Option<Crossing> = None;
+  //Starting inside shows up as the first crossing going out (or, for a plane we never cross, being behind it).
+  let starts_inside = match crossings.first(){
+    Some(c) => c.normal.dot(&ray.direction) > 0.0,
+    None => match shape{
+      Shape::Plane(p) => (ray.origin - p.point).dot(&p.normal) < 0.0,
+      _ => false,
+    },
+  };
+  if starts_inside{
+    enter = Some(boundary(f32::NEG_INFINITY, shape));
+  }
+  for crossing in crossings{
+    let entering = crossing.normal.dot(&ray.direction) < 0.0;
+    //Two entries or exits in a row (grazing hits or open surfaces), keep the first one.
+    if entering == enter.is_some(){
+      continue;
+    }
+    match enter.take(){
+      Some(e) => spans.push(Span {enter: e, exit: crossing}),
+      None => enter = Some(crossing),
+    }
+  }
+  if let Some(e) = enter{
+    spans.push(Span {enter: e, exit: boundary(f32::INFINITY, shape)});
+  }
+  return spans;
+}
+
+//A made up crossing at infinity, it is never shaded.
+fn boundary(t: f32, shape: &Shape) -> Crossing{
+  Crossing {t, normal: Vector3::new(0.0, 0.0, 0.0), uv: Vector2::new(0.0, 0.0), tangent: None, material: shape.material().clone(), detailed: true}
+}
+
+//Walks through the crossings of both sides in order and keeps the ones where the result goes in or out.
+//Surfaces of the subtracted side face the other way in the result.
+fn combine(a: Vec<Span>, b: Vec<Span>, operation: CsgOperation) -> Vec<Span>{
+  let mut events: Vec<(Crossing, bool, bool)> = Vec::new();
+  for span in a{
+    events.push((span.enter, true, true));
+    events.push((span.exit, true, false));
+  }
+  for span in b{
+    let mut enter = span.enter;
+    let mut exit = span.exit;
+    if operation == CsgOperation::Difference{
+      enter.flip();
+      exit.flip();
+    }
+    events.push((enter, false, true));
+    events.push((exit, false, false));
+  }
+  events.sort_by(|x, y| x.0.t.partial_cmp(&y.0.t).unwrap_or(std::cmp::Ordering::Equal));
+
+  let mut result: Vec<Span> = Vec::new();
+  let (mut in_a, mut in_b) = (false, false);
+  let mut enter: Option<Crossing> = None;
+  for (crossing, from_a, entering) in events{
+    if from_a {in_a = entering} else {in_b = entering}
+    let inside = operation.inside(in_a, in_b);
+    if inside == enter.is_some(){
+      continue;
+    }
+    match enter.take(){
+      Some(e) => result.push(Span {enter: e, exit: crossing}),
+      None => enter = Some(crossing),
+    }
+  }
+  return result;
+}
@@ -1,4 +1,39 @@
 use std::ops::{Add, Sub, Mul};
+use std::sync::Arc;
+
+//Import our model struct from model.rs
+use model::Model;
+
+//Import the tone mapping operators from tonemap.rs
+use tonemap::ToneMap;
+
+//Import the OpenEXR compression modes from exr.rs
+use exr::ExrCompression;
+
+//Import the render passes from passes.rs
+use passes::Pass;
+
+//Import the CSG objects from csg.rs
+use csg::Csg;
+
+//Import the signed distance field objects from sdf.rs
+use sdf::Sdf;
+
+//Import the surface textures from texture.rs
+use texture::Texture;
+
+//Import the procedural textures from procedural.rs
+use procedural::Pattern;
+
+//Import the fog and volumes from media.rs
+use media::Media;
+
+//Import the mesh instances from instance.rs
+use instance::Instance;
+
+//Import the acceleration structure from bvh.rs
+use bvh::Bvh;
+
 #[derive(Debug, Copy, Clone)]
 pub struct Light{
   pub intensity: f32,
@@ -13,43 +48,395 @@ impl Light{
   
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct Material{
   pub diffuse_color: Vector3,
   pub albedo: Vector4,
   pub specular_exponent: f32,
   pub refractive_index: f32,
+  pub id: u32,
+  //Textures are loaded once and shared by every material that uses them.
+  pub normal_map: Option<Arc<Texture>>,
+  pub normal_strength: f32,
+  pub bump_map: Option<Arc<Texture>>,
+  pub bump_scale: f32,
+  //Procedural patterns that replace the diffuse color and roughness or add bumps, looked up where the ray hits.
+  pub color_pattern: Option<Arc<Pattern>>,
+  pub roughness_pattern: Option<Arc<Pattern>>,
+  pub bump_pattern: Option<Arc<Pattern>>,
+  pub pattern_bump_scale: f32,
 }
 
 impl Material{
 
-  pub fn new(diffuse_color: Vector3, albedo: Vector4, specular_exponent: f32, refractive_index: f32) -> Self {
-    Material {diffuse_color, albedo, specular_exponent, refractive_index}
+  pub fn new(diffuse_color: Vector3, albedo: Vector4, specular_exponent: f32, refractive_index: f32, id: u32) -> Self {
+    Material {diffuse_color, albedo, specular_exponent, refractive_index, id, normal_map: None, normal_strength: 1.0, bump_map: None, bump_scale: 0.0, color_pattern: None, roughness_pattern: None, bump_pattern: None, pattern_bump_scale: 0.0}
+  }
+
+  //The Blinn-Phong exponent that gives about the same highlight as a roughness between 0 and 1 (alpha = roughness^2, exponent = 2/alpha^2 - 2).
+  //It's kept between 1 and 1400 so fully rough surfaces still get a wide highlight and smooth ones don't turn into a single bright pixel.
+  pub fn roughness_exponent(roughness: f32) -> f32 {
+    let roughness = roughness.max(0.0).min(1.0);
+    let alpha = (roughness*roughness).max(0.001);
+    return (2.0/(alpha*alpha) - 2.0).max(1.0).min(1400.0);
   }
   
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct Sphere{
   pub transform: Vector3,
+  pub transform_end: Vector3,
   pub radius: f32,
   pub material: Material,
+  pub id: u32,
 }
 
 impl Sphere{
 
-  pub fn new(transform: Vector3, radius: f32, material: Material) -> Self {
-    Sphere {transform, radius, material}
+  pub fn new(transform: Vector3, transform_end: Vector3, radius: f32, material: Material, id: u32) -> Self {
+    Sphere {transform, transform_end, radius, material, id}
+  }
+
+  //Position of the sphere at a point in the frame (0.0 = start, 1.0 = end).
+  pub fn transform_at(&self, time: f32) -> Vector3 {
+    lerp(self.transform, self.transform_end, time)
   }
   
 }
 
+//An infinite plane through a point, facing along its normal.
+#[derive(Debug, Clone)]
+pub struct Plane{
+  pub point: Vector3,
+  pub normal: Vector3,
+  pub material: Material,
+  pub id: u32,
+}
+
+impl Plane{
+
+  pub fn new(point: Vector3, normal: Vector3, material: Material, id: u32) -> Self {
+    Plane {point, normal: normal.normalize(), material, id}
+  }
+
+}
+
+//A box between two corners, optionally rotated around its center.
+#[derive(Debug, Clone)]
+pub struct Cuboid{
+  pub min: Vector3,
+  pub max: Vector3,
+  pub rotation: Matrix3,
+  pub material: Material,
+  pub id: u32,
+}
+
+impl Cuboid{
+
+  pub fn new(min: Vector3, max: Vector3, rotation: Matrix3, material: Material, id: u32) -> Self {
+    Cuboid {min, max, rotation, material, id}
+  }
+
+  pub fn center(&self) -> Vector3 {
+    (self.min + self.max)*0.5
+  }
+
+}
+
+//A cylinder standing on the center of its base, along y before the rotation.
+#[derive(Debug, Clone)]
+pub struct Cylinder{
+  pub transform: Vector3,
+  pub radius: f32,
+  pub height: f32,
+  pub capped: bool,
+  pub rotation: Matrix3,
+  pub material: Material,
+  pub id: u32,
+}
+
+impl Cylinder{
+
+  pub fn new(transform: Vector3, radius: f32, height: f32, capped: bool, rotation: Matrix3, material: Material, id: u32) -> Self {
+    Cylinder {transform, radius, height, capped, rotation, material, id}
+  }
+
+}
+
+//A cone standing on the center of its base with the tip at the top, along y before the rotation.
+#[derive(Debug, Clone)]
+pub struct Cone{
+  pub transform: Vector3,
+  pub radius: f32,
+  pub height: f32,
+  pub capped: bool,
+  pub rotation: Matrix3,
+  pub material: Material,
+  pub id: u32,
+}
+
+impl Cone{
+
+  pub fn new(transform: Vector3, radius: f32, height: f32, capped: bool, rotation: Matrix3, material: Material, id: u32) -> Self {
+    Cone {transform, radius, height, capped, rotation, material, id}
+  }
+
+}
+
+//A flat disk around its center, facing up (y) before the rotation.
+#[derive(Debug, Clone)]
+pub struct Disk{
+  pub transform: Vector3,
+  pub radius: f32,
+  pub rotation: Matrix3,
+  pub material: Material,
+  pub id: u32,
+}
+
+impl Disk{
+
+  pub fn new(transform: Vector3, radius: f32, rotation: Matrix3, material: Material, id: u32) -> Self {
+    Disk {transform, radius, rotation, material, id}
+  }
+
+}
+
+//A torus around its center, lying flat in xz before the rotation.
+//The major radius goes to the middle of the tube, the minor radius is the thickness of the tube.
+#[derive(Debug, Clone)]
+pub struct Torus{
+  pub transform: Vector3,
+  pub major_radius: f32,
+  pub minor_radius: f32,
+  pub rotation: Matrix3,
+  pub material: Material,
+  pub id: u32,
+}
+
+impl Torus{
+
+  pub fn new(transform: Vector3, major_radius: f32, minor_radius: f32, rotation: Matrix3, material: Material, id: u32) -> Self {
+    Torus {transform, major_radius, minor_radius, rotation, material, id}
+  }
+
+}
+
+//Any one of the primitives, used where different kinds of objects are mixed (like in CSG).
+#[derive(Debug, Clone)]
+pub enum Shape{
+  Sphere(Sphere),
+  Plane(Plane),
+  Cuboid(Cuboid),
+  Cylinder(Cylinder),
+  Cone(Cone),
+  Disk(Disk),
+  Torus(Torus),
+  Mesh(Model),
+}
+
+impl Shape{
+
+  pub fn material(&self) -> &Material {
+    match self {
+      Shape::Sphere(s) => &s.material,
+      Shape::Plane(p) => &p.material,
+      Shape::Cuboid(b) => &b.material,
+      Shape::Cylinder(c) => &c.material,
+      Shape::Cone(c) => &c.material,
+      Shape::Disk(d) => &d.material,
+      Shape::Torus(t) => &t.material,
+      Shape::Mesh(m) => &m.material,
+    }
+  }
+
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Camera{
+  pub transform: Vector3,
+  pub target: Vector3,
+  pub fov: f32,
+  pub shutter_open: f32,
+  pub shutter_close: f32,
+}
+
+impl Camera{
+
+  pub fn new(transform: Vector3, target: Vector3, fov: f32, shutter_open: f32, shutter_close: f32) -> Self {
+    Camera {transform, target, fov, shutter_open, shutter_close}
+  }
+
+  //Turns a direction in camera space (looking down -z) into world space, pointing at the target.
+  pub fn direction(&self, local: Vector3) -> Vector3 {
+    let forward = (self.target - self.transform).normalize();
+    let mut right = forward.cross(&Vector3::new(0.0, 1.0, 0.0)).normalize();
+    if right.magnitude() == 0.0 {
+      right = Vector3::new(1.0, 0.0, 0.0);
+    }
+    let up = right.cross(&forward);
+    return (right*local.x + up*local.y - forward*local.z).normalize();
+  }
+
+  //Maps a number in [0, 1) to a point in time while the shutter is open.
+  pub fn time_at(&self, sample: f32) -> f32 {
+    self.shutter_open + (self.shutter_close - self.shutter_open)*sample
+  }
+
+}
+
+//The closest surface a ray ran into.
+#[derive(Debug, Clone)]
+pub struct Hit{
+  pub point: Vector3,
+  pub normal: Vector3,
+  pub material: Material,
+  pub distance: f32,
+  pub object_id: u32,
+  pub uv: Vector2,
+  //The direction u grows in along the surface, with the side v grows on in a (None where the UVs don't give one).
+  pub tangent: Option<Vector4>,
+}
+
+impl Hit{
+
+  pub fn new(point: Vector3, normal: Vector3, material: Material, distance: f32, object_id: u32, uv: Vector2, tangent: Option<Vector4>) -> Self {
+    Hit {point, normal, material, distance, object_id, uv, tangent}
+  }
+
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Ray{
+  pub origin: Vector3,
+  pub direction: Vector3,
+  pub time: f32,
+}
+
+impl Ray{
+
+  pub fn new(origin: Vector3, direction: Vector3, time: f32) -> Self {
+    Ray {origin, direction, time}
+  }
+
+}
+
+//Everything that gets rendered, the interpreter builds this from the script.
+#[derive(Debug, Clone)]
+pub struct Scene{
+  pub camera: Camera,
+  pub lights: Vec<Light>,
+  pub spheres: Vec<Sphere>,
+  pub planes: Vec<Plane>,
+  pub boxes: Vec<Cuboid>,
+  pub cylinders: Vec<Cylinder>,
+  pub cones: Vec<Cone>,
+  pub disks: Vec<Disk>,
+  pub tori: Vec<Torus>,
+  pub csg: Vec<Csg>,
+  pub sdfs: Vec<Sdf>,
+  pub instances: Vec<Instance>,
+  pub instance_bvh: Bvh,
+  pub meshes: Vec<Model>,
+  pub media: Media,
+  pub background_color: Vector3,
+}
+
+//How the scene gets rendered and where the images go.
+#[derive(Debug, Clone)]
+pub struct Settings{
+  pub path_depth: i32,
+  pub fheight: usize,
+  pub fwidth: usize,
+  pub anti_alias: i32,
+  pub samples: i32,
+  pub frame_start: i32,
+  pub frame_end: i32,
+  pub outputs: Vec<String>,
+  pub exposure: f32,
+  pub tone_map: ToneMap,
+  pub dither: bool,
+  pub exr_compression: ExrCompression,
+  pub exr_half: bool,
+  pub passes: Vec<Pass>,
+  pub denoise_strength: f32,
+  pub denoise_iterations: i32,
+  pub adaptive_samples: i32,
+  pub adaptive_threshold: f32,
+  pub progressive: bool,
+  pub snapshot_interval: f32,
+  pub seed: u32,
+  pub checkpoint: String,
+  pub checkpoint_interval: f32,
+  //Every file the script loads (meshes, textures, glTF buffers), so a checkpoint notices when one of them changes.
+  pub assets: Vec<String>,
+  pub region: Region,
+  pub crop: bool,
+}
+
+//The part of the frame that gets rendered, in pixels (the end is exclusive). Without a region this is the whole frame.
+#[derive(Debug, Copy, Clone)]
+pub struct Region{
+  pub x0: usize,
+  pub y0: usize,
+  pub x1: usize,
+  pub y1: usize,
+}
+
+impl Region{
+
+  pub fn new(x0: usize, y0: usize, x1: usize, y1: usize) -> Self {
+    Region {x0, y0, x1, y1}
+  }
+
+  pub fn width(&self) -> usize {
+    self.x1 - self.x0
+  }
+
+  pub fn height(&self) -> usize {
+    self.y1 - self.y0
+  }
+
+  pub fn contains(&self, x: usize, y: usize) -> bool {
+    x >= self.x0 && x < self.x1 && y >= self.y0 && y < self.y1
+  }
+
+  //Copies the region out of a full frame image.
+  pub fn crop<T: Copy>(&self, image: &Vec<T>, fwidth: usize) -> Vec<T> {
+    let mut part: Vec<T> = Vec::with_capacity(self.width()*self.height());
+    for y in self.y0..self.y1 {
+      part.extend_from_slice(&image[self.x0 + y*fwidth..self.x1 + y*fwidth]);
+    }
+    return part;
+  }
+
+  //Copies a cropped image back to where it came from in the full frame.
+  pub fn paste<T: Copy>(&self, image: &mut Vec<T>, fwidth: usize, part: &Vec<T>) {
+    for y in self.y0..self.y1 {
+      let row = (y - self.y0)*self.width();
+      image[self.x0 + y*fwidth..self.x1 + y*fwidth].copy_from_slice(&part[row..row + self.width()]);
+    }
+  }
+
+}
+
+//Linear interpolation between two Vector3s.
+pub fn lerp(a: Vector3, b: Vector3, t: f32) -> Vector3 {
+  a + (b - a)*t
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Vector2{
   pub x: f32,
   pub y: f32,
 }
 
+impl Vector2{
+  pub fn new(x: f32, y: f32) -> Self {
+    Vector2 {x, y}
+  }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Vector3i{
   pub x: i32,
@@ -159,6 +546,51 @@ impl Mul<f32> for Vector3{
 
 }
 
+//A 3x3 matrix, only used for rotations so far.
+#[derive(Debug, Copy, Clone)]
+pub struct Matrix3{
+  pub m: [[f32; 3]; 3],
+}
+
+impl Matrix3{
+
+  pub fn identity() -> Self {
+    Matrix3 {m: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]}
+  }
+
+  //Rotation from angles in degrees around x, then y, then z.
+  pub fn rotation(degrees: Vector3) -> Self {
+    let (sx, cx) = degrees.x.to_radians().sin_cos();
+    let (sy, cy) = degrees.y.to_radians().sin_cos();
+    let (sz, cz) = degrees.z.to_radians().sin_cos();
+    let x = Matrix3 {m: [[1.0, 0.0, 0.0], [0.0, cx, -sx], [0.0, sx, cx]]};
+    let y = Matrix3 {m: [[cy, 0.0, sy], [0.0, 1.0, 0.0], [-sy, 0.0, cy]]};
+    let z = Matrix3 {m: [[cz, -sz, 0.0], [sz, cz, 0.0], [0.0, 0.0, 1.0]]};
+    z.multiply(&y).multiply(&x)
+  }
+
+  pub fn multiply(&self, other: &Matrix3) -> Matrix3 {
+    let mut m = [[0.0; 3]; 3];
+    for i in 0..3 {
+      for j in 0..3 {
+        m[i][j] = self.m[i][0]*other.m[0][j] + self.m[i][1]*other.m[1][j] + self.m[i][2]*other.m[2][j];
+      }
+    }
+    Matrix3 {m}
+  }
+
+  pub fn transpose(&self) -> Matrix3 {
+    let m = self.m;
+    Matrix3 {m: [[m[0][0], m[1][0], m[2][0]], [m[0][1], m[1][1], m[2][1]], [m[0][2], m[1][2], m[2][2]]]}
+  }
+
+  pub fn apply(&self, v: Vector3) -> Vector3 {
+    let m = self.m;
+    Vector3::new(m[0][0]*v.x + m[0][1]*v.y + m[0][2]*v.z, m[1][0]*v.x + m[1][1]*v.y + m[1][2]*v.z, m[2][0]*v.x + m[2][1]*v.y + m[2][2]*v.z)
+  }
+
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Vector4{
   pub x: f32,
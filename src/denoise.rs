@@ -0,0 +1,80 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+
+//Import the render passes from passes.rs
+use passes::Aov;
+
+//B3 spline weights of the a-trous kernel.
+const KERNEL: [f32; 5] = [1.0/16.0, 1.0/4.0, 3.0/8.0, 1.0/4.0, 1.0/16.0];
+const NORMAL_POWER: f32 = 64.0;
+const DEPTH_SIGMA: f32 = 0.05;
+const ALBEDO_SIGMA: f32 = 0.1;
+
+//Edge avoiding a-trous wavelet filter, guided by the normal, albedo and depth of the first hit.
+//The albedo is divided out before filtering and multiplied back in after, so texture detail isn't blurred.
+//Strength is how different two colors may be (after compressing them to 0-1) and still get mixed.
+pub fn denoise(framebuffer: &Vec<Vector3>, aovs: &Vec<Aov>, fheight: usize, fwidth: usize, strength: f32, iterations: i32) -> Vec<Vector3>{
+  let demodulate = |c: f32, a: f32| if a > 0.01 {c/a} else {c};
+  let mut image: Vec<Vector3> = framebuffer.iter().zip(aovs.iter()).map(|(c, a)| {
+    Vector3::new(demodulate(c.x, a.albedo.x), demodulate(c.y, a.albedo.y), demodulate(c.z, a.albedo.z))
+  }).collect();
+
+  for iteration in 0..iterations {
+    let step = 1 << iteration;
+    //Each pass reaches twice as far, so the color test gets stricter to keep the detail found by the earlier ones.
+    let sigma = strength*strength*(0.5 as f32).powi(iteration);
+    let mut filtered: Vec<Vector3> = vec![Vector3::new(0.0, 0.0, 0.0); image.len()];
+    for y in 0..fheight {
+      for x in 0..fwidth {
+        let p = x + y*fwidth;
+        let center = image[p];
+        let center_compressed = compress(center);
+        let guide = &aovs[p];
+        let mut sum = Vector3::new(0.0, 0.0, 0.0);
+        let mut weight_sum = 0.0;
+        for j in 0..5 {
+          let qy = y as i64 + (j as i64 - 2)*step;
+          if qy < 0 || qy >= fheight as i64 {
+            continue;
+          }
+          for i in 0..5 {
+            let qx = x as i64 + (i as i64 - 2)*step;
+            if qx < 0 || qx >= fwidth as i64 {
+              continue;
+            }
+            let q = qx as usize + qy as usize*fwidth;
+            let other = &aovs[q];
+            let color_distance = distance_squared(center_compressed, compress(image[q]));
+            let albedo_distance = distance_squared(guide.albedo, other.albedo);
+            let depth_distance = (guide.depth - other.depth).abs()/f32::max(f32::max(guide.depth.abs(), other.depth.abs()), 1e-4);
+            let normal_weight = if guide.object_id == 0 && other.object_id == 0 {1.0} else {guide.normal.dot(&other.normal).max(0.0).powf(NORMAL_POWER)};
+            let weight = KERNEL[i]*KERNEL[j]
+              *(-color_distance/f32::max(sigma, 1e-8)).exp()
+              *(-albedo_distance/(ALBEDO_SIGMA*ALBEDO_SIGMA)).exp()
+              *(-depth_distance/DEPTH_SIGMA).exp()
+              *normal_weight;
+            sum = sum + image[q]*weight;
+            weight_sum += weight;
+          }
+        }
+        filtered[p] = if weight_sum > 0.0 {sum*(1.0/weight_sum)} else {center};
+      }
+    }
+    image = filtered;
+  }
+
+  return image.iter().zip(aovs.iter()).map(|(c, a)| {
+    let modulate = |c: f32, a: f32| if a > 0.01 {c*a} else {c};
+    Vector3::new(modulate(c.x, a.albedo.x), modulate(c.y, a.albedo.y), modulate(c.z, a.albedo.z))
+  }).collect();
+}
+
+//Squeezes radiance into 0-1 so bright pixels don't dominate the color test.
+fn compress(c: Vector3) -> Vector3 {
+  Vector3::new(c.x/(1.0 + c.x.abs()), c.y/(1.0 + c.y.abs()), c.z/(1.0 + c.z.abs()))
+}
+
+fn distance_squared(a: Vector3, b: Vector3) -> f32 {
+  let d = a - b;
+  d.dot(&d)
+}
@@ -0,0 +1,199 @@
+//Import all of the standard libraries we need.
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExrCompression{
+  None,
+  Rle,
+}
+
+//One named channel of float data, layers are written as "layer.channel" (the beauty uses plain R, G and B).
+//Integer channels (like IDs) are always written as UINT, half floats can't hold whole numbers above 2048.
+#[derive(Debug, Clone)]
+pub struct ExrChannel{
+  pub name: String,
+  pub data: Vec<f32>,
+  pub integer: bool,
+}
+
+impl ExrChannel{
+
+  pub fn new(name: &str, data: Vec<f32>) -> Self {
+    ExrChannel {name: name.to_string(), data, integer: false}
+  }
+
+  pub fn integer(name: &str, data: Vec<f32>) -> Self {
+    ExrChannel {name: name.to_string(), data, integer: true}
+  }
+
+}
+
+//Writes a single part scanline OpenEXR file, with half or float channels (and UINT for integer ones) and one scanline per chunk.
+pub fn write_exr(filename: &str, channels: &Vec<ExrChannel>, fheight: usize, fwidth: usize, compression: ExrCompression, half: bool) -> io::Result<()>{
+  //The channel list has to be sorted by name and the pixel data follows the same order.
+  let mut channels: Vec<&ExrChannel> = channels.iter().collect();
+  channels.sort_by(|a, b| a.name.cmp(&b.name));
+  let pixel_type = |channel: &ExrChannel| -> i32 {if channel.integer {0} else if half {1} else {2}};
+
+  let mut header: Vec<u8> = Vec::new();
+  //Magic number and version 2 (single part scanline).
+  header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+  let mut chlist: Vec<u8> = Vec::new();
+  for channel in &channels {
+    chlist.extend_from_slice(channel.name.as_bytes());
+    chlist.push(0);
+    chlist.extend_from_slice(&pixel_type(channel).to_le_bytes());
+    //pLinear and three reserved bytes, then the x and y sampling.
+    chlist.extend_from_slice(&[0, 0, 0, 0]);
+    chlist.extend_from_slice(&1i32.to_le_bytes());
+    chlist.extend_from_slice(&1i32.to_le_bytes());
+  }
+  chlist.push(0);
+  attribute(&mut header, "channels", "chlist", &chlist);
+  attribute(&mut header, "compression", "compression", &[if compression == ExrCompression::Rle {1} else {0}]);
+  let mut window: Vec<u8> = Vec::new();
+  for v in [0, 0, fwidth as i32 - 1, fheight as i32 - 1].iter() {
+    window.extend_from_slice(&v.to_le_bytes());
+  }
+  attribute(&mut header, "dataWindow", "box2i", &window);
+  attribute(&mut header, "displayWindow", "box2i", &window);
+  attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+  attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+  attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+  attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+  header.push(0);
+
+  //Build every scanline chunk first so we know the offsets.
+  let mut chunks: Vec<Vec<u8>> = Vec::with_capacity(fheight);
+  for y in 0..fheight {
+    let mut line: Vec<u8> = Vec::with_capacity(channels.len()*fwidth*4);
+    for channel in &channels {
+      for value in &channel.data[y*fwidth..(y + 1)*fwidth] {
+        if channel.integer {
+          line.extend_from_slice(&(*value as u32).to_le_bytes());
+        }
+        else if half {
+          line.extend_from_slice(&f32_to_half(*value).to_le_bytes());
+        }
+        else {
+          line.extend_from_slice(&value.to_le_bytes());
+        }
+      }
+    }
+    if compression == ExrCompression::Rle {
+      //Readers expect the raw data whenever compressing doesn't make it smaller.
+      let compressed = rle_compress(&line);
+      if compressed.len() < line.len() {
+        line = compressed;
+      }
+    }
+    let mut chunk: Vec<u8> = Vec::with_capacity(line.len() + 8);
+    chunk.extend_from_slice(&(y as i32).to_le_bytes());
+    chunk.extend_from_slice(&(line.len() as i32).to_le_bytes());
+    chunk.extend_from_slice(&line);
+    chunks.push(chunk);
+  }
+
+  let mut file = BufWriter::new(File::create(filename)?);
+  file.write_all(&header)?;
+  let mut offset = (header.len() + fheight*8) as u64;
+  for chunk in &chunks {
+    file.write_all(&offset.to_le_bytes())?;
+    offset += chunk.len() as u64;
+  }
+  for chunk in &chunks {
+    file.write_all(chunk)?;
+  }
+  Ok(())
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]){
+  header.extend_from_slice(name.as_bytes());
+  header.push(0);
+  header.extend_from_slice(kind.as_bytes());
+  header.push(0);
+  header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+  header.extend_from_slice(value);
+}
+
+//Converts to a 16 bit float, rounding to nearest even.
+pub fn f32_to_half(value: f32) -> u16{
+  let bits = value.to_bits();
+  let sign = ((bits >> 16) & 0x8000) as u16;
+  let exponent = ((bits >> 23) & 0xff) as i32;
+  let mantissa = bits & 0x7fffff;
+  //Infinity and NaN.
+  if exponent == 0xff {
+    return sign | 0x7c00 | if mantissa != 0 {0x200} else {0};
+  }
+  let exponent = exponent - 127 + 15;
+  if exponent >= 0x1f {
+    return sign | 0x7c00;
+  }
+  if exponent <= 0 {
+    //Too small even for a denormal half.
+    if exponent < -10 {
+      return sign;
+    }
+    let mantissa = mantissa | 0x800000;
+    let shift = (14 - exponent) as u32;
+    let half = mantissa >> shift;
+    let remainder = mantissa & ((1 << shift) - 1);
+    let midpoint = 1 << (shift - 1);
+    let round = (remainder > midpoint || (remainder == midpoint && (half & 1) == 1)) as u32;
+    return sign | (half + round) as u16;
+  }
+  let half = ((exponent as u32) << 10) | (mantissa >> 13);
+  let remainder = mantissa & 0x1fff;
+  let round = (remainder > 0x1000 || (remainder == 0x1000 && (half & 1) == 1)) as u32;
+  //A carry out of the mantissa correctly bumps the exponent (up to infinity).
+  return sign | (half + round) as u16;
+}
+
+//OpenEXR RLE: split the bytes into two halves, delta encode them, then run length encode.
+fn rle_compress(data: &Vec<u8>) -> Vec<u8>{
+  let n = data.len();
+  let mut tmp: Vec<u8> = vec![0; n];
+  let half = (n + 1)/2;
+  for i in 0..n {
+    if i % 2 == 0 {
+      tmp[i/2] = data[i];
+    }
+    else {
+      tmp[half + i/2] = data[i];
+    }
+  }
+  let mut previous = tmp[0];
+  for i in 1..n {
+    let current = tmp[i];
+    tmp[i] = (current as i32 - previous as i32 + 128 + 256) as u8;
+    previous = current;
+  }
+
+  //Runs of 3 or more are stored as (length - 1, value), everything else as (-length, bytes...).
+  let mut out: Vec<u8> = Vec::new();
+  let mut run_start = 0;
+  let mut run_end = 1;
+  while run_start < n {
+    while run_end < n && tmp[run_start] == tmp[run_end] && run_end - run_start - 1 < 127 {
+      run_end += 1;
+    }
+    if run_end - run_start >= 3 {
+      out.push((run_end - run_start - 1) as u8);
+      out.push(tmp[run_start]);
+      run_start = run_end;
+    }
+    else {
+      while run_end < n && (run_end + 1 >= n || tmp[run_end] != tmp[run_end + 1] || run_end + 2 >= n || tmp[run_end + 1] != tmp[run_end + 2]) && run_end - run_start < 127 {
+        run_end += 1;
+      }
+      out.push((-((run_end - run_start) as i32)) as u8);
+      out.extend_from_slice(&tmp[run_start..run_end]);
+      run_start = run_end;
+    }
+    run_end += 1;
+  }
+  return out;
+}
@@ -0,0 +1,99 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+
+//Import the render passes from passes.rs
+use passes::Aov;
+use passes::AovAccumulator;
+
+//Import the luminance from tonemap.rs
+use tonemap::luminance;
+
+//Everything one render pass found out about a pixel, sums so it can be added to what we already have.
+#[derive(Debug, Copy, Clone)]
+pub struct PixelSamples{
+  pub color: Vector3,
+  pub luminance: f32,
+  pub luminance_squared: f32,
+  pub count: u32,
+  pub aov: AovAccumulator,
+}
+
+impl PixelSamples{
+
+  pub fn new() -> Self {
+    PixelSamples {color: Vector3::new(0.0, 0.0, 0.0), luminance: 0.0, luminance_squared: 0.0, count: 0, aov: AovAccumulator::new()}
+  }
+
+  //Adds one sample (the average of the rays cast for it).
+  pub fn add(&mut self, color: Vector3) {
+    let l = luminance(color);
+    self.color = self.color + color;
+    self.luminance += l;
+    self.luminance_squared += l*l;
+    self.count += 1;
+  }
+
+}
+
+//The accumulated samples of the whole image, every pixel can have a different number of samples.
+#[derive(Debug, Clone)]
+pub struct Film{
+  pub pixels: Vec<PixelSamples>,
+}
+
+impl Film{
+
+  pub fn new(fwidth: usize, fheight: usize) -> Self {
+    Film {pixels: vec![PixelSamples::new(); fwidth*fheight]}
+  }
+
+  pub fn add(&mut self, index: usize, samples: &PixelSamples) {
+    let pixel = &mut self.pixels[index];
+    pixel.color = pixel.color + samples.color;
+    pixel.luminance += samples.luminance;
+    pixel.luminance_squared += samples.luminance_squared;
+    pixel.count += samples.count;
+    pixel.aov.merge(&samples.aov);
+  }
+
+  //Standard error of the mean luminance relative to its brightness (dark pixels are allowed a small absolute error).
+  pub fn error(&self, index: usize) -> f32 {
+    let pixel = &self.pixels[index];
+    if pixel.count < 2 {
+      return f32::MAX;
+    }
+    let n = pixel.count as f32;
+    let mean = pixel.luminance/n;
+    let variance = f32::max(pixel.luminance_squared/n - mean*mean, 0.0)*n/(n - 1.0);
+    return (variance/n).sqrt()/(mean + 0.05);
+  }
+
+  //Decides how many more samples each pixel gets: pixels still noisier than the threshold double their samples (up to the budget).
+  pub fn adaptive_schedule(&self, threshold: f32, max_samples: u32) -> Vec<u32> {
+    let mut schedule: Vec<u32> = vec![0; self.pixels.len()];
+    for i in 0..self.pixels.len() {
+      let count = self.pixels[i].count;
+      if count < max_samples && self.error(i) > threshold {
+        schedule[i] = u32::min(u32::max(count, 1), max_samples - count);
+      }
+    }
+    return schedule;
+  }
+
+  //The averaged framebuffer and render passes.
+  pub fn resolve(&self, background_color: Vector3) -> (Vec<Vector3>, Vec<Aov>) {
+    let mut framebuffer: Vec<Vector3> = Vec::with_capacity(self.pixels.len());
+    let mut aovs: Vec<Aov> = Vec::with_capacity(self.pixels.len());
+    for pixel in &self.pixels {
+      if pixel.count == 0 {
+        framebuffer.push(Vector3::new(0.0, 0.0, 0.0));
+        aovs.push(Aov::background(background_color));
+        continue;
+      }
+      framebuffer.push(pixel.color*(1.0/pixel.count as f32));
+      aovs.push(pixel.aov.finish(pixel.count));
+    }
+    return (framebuffer, aovs);
+  }
+
+}
@@ -0,0 +1,447 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Vector3i;
+use definitions::Vector4;
+use definitions::Vector2;
+use definitions::Material;
+use definitions::Light;
+use definitions::Camera;
+
+//Import the mesh data from model.rs
+use model::MeshData;
+
+//Import the JSON parser from json.rs
+use json::Json;
+
+use std::fs;
+use std::path::Path;
+
+//Directional lights become point lights this far away, our lights don't fade with distance so it looks the same.
+const SUN_DISTANCE: f32 = 10000.0;
+//How deep the node hierarchy may go, so a broken file with a loop in it can't hang us.
+const MAX_DEPTH: usize = 64;
+//Accessors without a buffer view have no data to check their count against, this many numbers is already far more than a real one needs.
+const MAX_EMPTY_ACCESSOR: usize = 1 << 24;
+
+//A column major 4x4 matrix, the way glTF stores them.
+type Matrix4 = [f32; 16];
+
+const IDENTITY: Matrix4 = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+
+//Everything a glTF file adds to the scene, with the node transforms already applied.
+//Materials have no IDs yet and every mesh points at one of them.
+pub struct GltfScene{
+  pub meshes: Vec<(MeshData, usize)>,
+  pub materials: Vec<(String, Material)>,
+  pub lights: Vec<Light>,
+  pub camera: Option<Camera>,
+  //The buffer files next to the .gltf that were read.
+  pub files: Vec<String>,
+}
+
+//Reads a .gltf (with its .bin files or data URIs) or a .glb file.
+//The whole scene is moved by offset and scaled by scale around the origin first.
+pub fn load_gltf(filename: &str, offset: Vector3, scale: f32) -> Result<GltfScene, String> {
+  let data = fs::read(filename).map_err(|e| e.to_string())?;
+  let (gltf, binary) = if data.starts_with(b"glTF") {read_glb(&data)?} else {
+    (Json::parse(std::str::from_utf8(&data).map_err(|_| "the file isn't valid text")?)?, None)
+  };
+  let version = gltf.get("asset").get("version").as_str().unwrap_or("");
+  if !version.starts_with("2.") {
+    return Err(format!("only glTF 2.0 is supported (this is version '{}')", version));
+  }
+  let directory = Path::new(filename).parent().unwrap_or(Path::new("."));
+  let buffers = read_buffers(&gltf, directory, binary)?;
+
+  let files = gltf.get("buffers").items().iter().filter_map(|b| b.get("uri").as_str()).filter(|uri| !uri.starts_with("data:")).map(|uri| directory.join(percent_decode(uri)).to_string_lossy().to_string()).collect();
+  let mut scene = GltfScene {meshes: Vec::new(), materials: Vec::new(), lights: Vec::new(), camera: None, files};
+  for (i, material) in gltf.get("materials").items().iter().enumerate() {
+    let name = material.get("name").as_str().map_or(format!("material{}", i), |n| n.to_string());
+    scene.materials.push((name, convert_material(material)));
+  }
+  //Primitives without a material get the glTF default, which is plain white.
+  scene.materials.push(("default".to_string(), convert_material(&Json::Null)));
+
+  let root = multiply(&translation(offset), &[scale, 0.0, 0.0, 0.0, 0.0, scale, 0.0, 0.0, 0.0, 0.0, scale, 0.0, 0.0, 0.0, 0.0, 1.0]);
+  for node in root_nodes(&gltf) {
+    visit(&gltf, &buffers, node, &root, 0, &mut scene)?;
+  }
+  return Ok(scene);
+}
+
+//The nodes of the default scene, or every node that isn't a child when the file has no scenes.
+fn root_nodes(gltf: &Json) -> Vec<usize> {
+  let scenes = gltf.get("scenes");
+  if !scenes.is_null() {
+    let scene = scenes.at(gltf.get("scene").as_usize().unwrap_or(0));
+    return scene.get("nodes").items().iter().filter_map(|n| n.as_usize()).collect();
+  }
+  let nodes = gltf.get("nodes").items();
+  let children: Vec<usize> = nodes.iter().flat_map(|n| n.get("children").items().iter().filter_map(|c| c.as_usize())).collect();
+  (0..nodes.len()).filter(|i| !children.contains(i)).collect()
+}
+
+fn visit(gltf: &Json, buffers: &Vec<Vec<u8>>, index: usize, parent: &Matrix4, depth: usize, scene: &mut GltfScene) -> Result<(), String> {
+  if depth > MAX_DEPTH {
+    return Err("the node hierarchy is too deep (or has a loop)".to_string());
+  }
+  let node = gltf.get("nodes").at(index);
+  if node.is_null() {
+    return Err(format!("node {} doesn't exist", index));
+  }
+  let world = multiply(parent, &local_transform(node));
+
+  if let Some(mesh) = node.get("mesh").as_usize() {
+    for primitive in gltf.get("meshes").at(mesh).get("primitives").items() {
+      let material = primitive.get("material").as_usize().filter(|&m| m < scene.materials.len() - 1).unwrap_or(scene.materials.len() - 1);
+      let base = scene.materials[material].1.diffuse_color;
+      if let Some(data) = read_primitive(gltf, buffers, primitive, &world, base)? {
+        scene.meshes.push((data, material));
+      }
+    }
+  }
+  if let Some(camera) = node.get("camera").as_usize() {
+    //Only the first camera is used, our camera always keeps the world up direction so a rolled camera loses its roll.
+    let camera = gltf.get("cameras").at(camera);
+    match camera.get("type").as_str() {
+      Some("perspective") if scene.camera.is_none() => {
+        let position = transform_point(&world, Vector3::new(0.0, 0.0, 0.0));
+        let forward = transform_direction(&world, Vector3::new(0.0, 0.0, -1.0)).normalize();
+        let fov = camera.get("perspective").get("yfov").as_f32_or(0.8);
+        scene.camera = Some(Camera::new(position, position + forward, fov, 0.0, 0.0));
+      },
+      Some("perspective") => (),
+      _ => eprintln!("Only perspective glTF cameras are supported, skipping camera {}.", index),
+    }
+  }
+  if let Some(light) = node.get("extensions").get("KHR_lights_punctual").get("light").as_usize() {
+    let light = gltf.get("extensions").get("KHR_lights_punctual").get("lights").at(light);
+    //Our lights have no color and no falloff, so the intensity is scaled by how bright the color is and spot lights shine everywhere.
+    let color = light.get("color").as_floats().unwrap_or(vec![1.0, 1.0, 1.0]);
+    let intensity = light.get("intensity").as_f32_or(1.0)*color.iter().sum::<f32>()/(color.len().max(1) as f32);
+    let position = transform_point(&world, Vector3::new(0.0, 0.0, 0.0));
+    match light.get("type").as_str() {
+      Some("directional") => {
+        let direction = transform_direction(&world, Vector3::new(0.0, 0.0, -1.0)).normalize();
+        scene.lights.push(Light::new(position - direction*SUN_DISTANCE, intensity));
+      },
+      Some("point") | Some("spot") => scene.lights.push(Light::new(position, intensity)),
+      _ => eprintln!("Unknown glTF light type on node {}.", index),
+    }
+  }
+  for child in node.get("children").items() {
+    if let Some(child) = child.as_usize() {
+      visit(gltf, buffers, child, &world, depth + 1, scene)?;
+    }
+  }
+  Ok(())
+}
+
+//Turns one primitive into a mesh in world space, points and lines are skipped.
+fn read_primitive(gltf: &Json, buffers: &Vec<Vec<u8>>, primitive: &Json, world: &Matrix4, base: Vector3) -> Result<Option<MeshData>, String> {
+  let attributes = primitive.get("attributes");
+  let positions = match attributes.get("POSITION").as_usize() {
+    Some(accessor) => read_accessor(gltf, buffers, accessor)?,
+    None => return Ok(None),
+  };
+  let count = positions.len()/3;
+  let indices: Vec<usize> = match primitive.get("indices").as_usize() {
+    Some(accessor) => read_accessor(gltf, buffers, accessor)?.iter().map(|&i| i as usize).collect(),
+    None => (0..count).collect(),
+  };
+  if indices.iter().any(|&i| i >= count) {
+    return Err("a primitive uses a vertex that doesn't exist".to_string());
+  }
+
+  //A mirroring transform turns the triangles inside out, so their order gets swapped back.
+  let (c0, c1, c2) = (Vector3::new(world[0], world[1], world[2]), Vector3::new(world[4], world[5], world[6]), Vector3::new(world[8], world[9], world[10]));
+  let flip = c0.dot(&c1.cross(&c2)) < 0.0;
+  let mut triangles: Vec<[usize; 3]> = Vec::new();
+  match primitive.get("mode").as_usize().unwrap_or(4) {
+    4 => for t in indices.chunks(3).filter(|t| t.len() == 3) {
+      triangles.push([t[0], t[1], t[2]]);
+    },
+    5 => for i in 2..indices.len() {
+      //Every other triangle of a strip is wound the other way.
+      triangles.push(if i % 2 == 0 {[indices[i - 2], indices[i - 1], indices[i]]} else {[indices[i - 1], indices[i - 2], indices[i]]});
+    },
+    6 => for i in 2..indices.len() {
+      triangles.push([indices[0], indices[i - 1], indices[i]]);
+    },
+    _ => return Ok(None),
+  }
+
+  let mut mesh = MeshData::new(Vec::new(), Vec::new());
+  mesh.verts = positions.chunks(3).map(|p| transform_point(world, Vector3::new(p[0] as f32, p[1] as f32, p[2] as f32))).collect();
+  mesh.faces = triangles.iter().map(|t| if flip {Vector3i::new(t[0] as i32, t[2] as i32, t[1] as i32)} else {Vector3i::new(t[0] as i32, t[1] as i32, t[2] as i32)}).collect();
+  if let Some(accessor) = attributes.get("NORMAL").as_usize() {
+    //Normals go through the cofactor matrix, which keeps them at right angles to the surface under any scaling.
+    let sign = if flip {-1.0} else {1.0};
+    let normals = read_accessor(gltf, buffers, accessor)?;
+    mesh.normals = normals.chunks(3).map(|n| ((c1.cross(&c2)*(n[0] as f32) + c2.cross(&c0)*(n[1] as f32) + c0.cross(&c1)*(n[2] as f32))*sign).normalize()).collect();
+  }
+  if let Some(accessor) = attributes.get("TEXCOORD_0").as_usize() {
+    //glTF puts v = 0 at the top of the texture, ours is at the bottom.
+    mesh.uvs = read_accessor(gltf, buffers, accessor)?.chunks(2).map(|t| Vector2::new(t[0] as f32, 1.0 - t[1] as f32)).collect();
+  }
+  if let Some(accessor) = attributes.get("COLOR_0").as_usize() {
+    //Vertex colors multiply the base color, they can be RGB or RGBA.
+    let size = components(gltf.get("accessors").at(accessor))?;
+    mesh.colors = read_accessor(gltf, buffers, accessor)?.chunks(size).map(|c| Vector3::new(c[0] as f32*base.x, c[1] as f32*base.y, c[2] as f32*base.z)).collect();
+  }
+  //An attribute with the wrong length is left out rather than read past its end.
+  if mesh.normals.len() != count {mesh.normals.clear();}
+  if mesh.uvs.len() != count {mesh.uvs.clear();}
+  if mesh.colors.len() != count {mesh.colors.clear();}
+  return Ok(Some(mesh));
+}
+
+//Metallic-roughness doesn't map exactly onto our materials, so this is an approximation:
+//smooth metals become mirrors, rough surfaces get a wide dull highlight and transmission becomes refraction.
+fn convert_material(material: &Json) -> Material {
+  let pbr = material.get("pbrMetallicRoughness");
+  let base = pbr.get("baseColorFactor").as_floats().unwrap_or(vec![1.0, 1.0, 1.0, 1.0]);
+  let metallic = pbr.get("metallicFactor").as_f32_or(1.0).max(0.0).min(1.0);
+  let roughness = pbr.get("roughnessFactor").as_f32_or(1.0).max(0.0).min(1.0);
+  let extensions = material.get("extensions");
+  let transmission = extensions.get("KHR_materials_transmission").get("transmissionFactor").as_f32_or(0.0);
+  let ior = extensions.get("KHR_materials_ior").get("ior").as_f32_or(1.5);
+
+  let reflect = metallic*(1.0 - roughness)*(1.0 - transmission);
+  let diffuse = (1.0 - reflect)*(1.0 - transmission);
+  let specular = 0.5*(1.0 - roughness);
+  let exponent = Material::roughness_exponent(roughness);
+  let color = Vector3::new(*base.get(0).unwrap_or(&1.0), *base.get(1).unwrap_or(&1.0), *base.get(2).unwrap_or(&1.0));
+  let index = if transmission > 0.0 {ior} else {1.0};
+  Material::new(color, Vector4::new(diffuse, specular, reflect, transmission), exponent, index, 0)
+}
+
+fn local_transform(node: &Json) -> Matrix4 {
+  if let Some(m) = node.get("matrix").as_floats() {
+    if m.len() == 16 {
+      let mut matrix = IDENTITY;
+      matrix.copy_from_slice(&m);
+      return matrix;
+    }
+  }
+  let t = node.get("translation").as_floats().unwrap_or(vec![0.0, 0.0, 0.0]);
+  let r = node.get("rotation").as_floats().unwrap_or(vec![0.0, 0.0, 0.0, 1.0]);
+  let s = node.get("scale").as_floats().unwrap_or(vec![1.0, 1.0, 1.0]);
+  if t.len() != 3 || r.len() != 4 || s.len() != 3 {
+    return IDENTITY;
+  }
+  //The rotation is a unit quaternion (x, y, z, w).
+  let (x, y, z, w) = (r[0], r[1], r[2], r[3]);
+  [
+    (1.0 - 2.0*(y*y + z*z))*s[0], 2.0*(x*y + z*w)*s[0], 2.0*(x*z - y*w)*s[0], 0.0,
+    2.0*(x*y - z*w)*s[1], (1.0 - 2.0*(x*x + z*z))*s[1], 2.0*(y*z + x*w)*s[1], 0.0,
+    2.0*(x*z + y*w)*s[2], 2.0*(y*z - x*w)*s[2], (1.0 - 2.0*(x*x + y*y))*s[2], 0.0,
+    t[0], t[1], t[2], 1.0,
+  ]
+}
+
+fn translation(offset: Vector3) -> Matrix4 {
+  let mut matrix = IDENTITY;
+  matrix[12] = offset.x;
+  matrix[13] = offset.y;
+  matrix[14] = offset.z;
+  matrix
+}
+
+fn multiply(a: &Matrix4, b: &Matrix4) -> Matrix4 {
+  let mut result = [0.0; 16];
+  for column in 0..4 {
+    for row in 0..4 {
+      result[column*4 + row] = (0..4).map(|k| a[k*4 + row]*b[column*4 + k]).sum();
+    }
+  }
+  result
+}
+
+fn transform_point(m: &Matrix4, p: Vector3) -> Vector3 {
+  transform_direction(m, p) + Vector3::new(m[12], m[13], m[14])
+}
+
+fn transform_direction(m: &Matrix4, v: Vector3) -> Vector3 {
+  Vector3::new(
+    m[0]*v.x + m[4]*v.y + m[8]*v.z,
+    m[1]*v.x + m[5]*v.y + m[9]*v.z,
+    m[2]*v.x + m[6]*v.y + m[10]*v.z,
+  )
+}
+
+//A .glb file is a small header followed by a JSON chunk and an optional binary chunk.
+fn read_glb(data: &[u8]) -> Result<(Json, Option<Vec<u8>>), String> {
+  let word = |at: usize| -> Result<u32, String> {
+    data.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or("the GLB file ends too early".to_string())
+  };
+  if word(4)? != 2 {
+    return Err("only version 2 GLB files are supported".to_string());
+  }
+  let mut json: Option<Json> = None;
+  let mut binary: Option<Vec<u8>> = None;
+  let mut position = 12;
+  let end = (word(8)? as usize).min(data.len());
+  while position + 8 <= end {
+    let length = word(position)? as usize;
+    let kind = word(position + 4)?;
+    let chunk = data.get(position + 8..position + 8 + length).ok_or("a GLB chunk ends past the end of the file")?;
+    match kind {
+      0x4E4F534A => json = Some(Json::parse(std::str::from_utf8(chunk).map_err(|_| "the JSON chunk isn't valid text")?)?),
+      0x004E4942 if binary.is_none() => binary = Some(chunk.to_vec()),
+      _ => (),
+    }
+    position += 8 + length;
+  }
+  match json {
+    Some(json) => Ok((json, binary)),
+    None => Err("the GLB file has no JSON chunk".to_string()),
+  }
+}
+
+//Buffers come from files next to the .gltf, base64 data URIs, or the binary chunk of a .glb.
+fn read_buffers(gltf: &Json, directory: &Path, binary: Option<Vec<u8>>) -> Result<Vec<Vec<u8>>, String> {
+  let mut binary = binary;
+  let mut buffers = Vec::new();
+  for (i, buffer) in gltf.get("buffers").items().iter().enumerate() {
+    let data = match buffer.get("uri").as_str() {
+      Some(uri) if uri.starts_with("data:") => {
+        let start = uri.find(";base64,").ok_or(format!("buffer {} has a data URI that isn't base64", i))? + 8;
+        base64(&uri[start..]).ok_or(format!("buffer {} has broken base64 data", i))?
+      },
+      Some(uri) => {
+        let path = directory.join(percent_decode(uri));
+        fs::read(&path).map_err(|e| format!("can't read {}: {}", path.display(), e))?
+      },
+      None if i == 0 => binary.take().ok_or("the first buffer has no URI and there's no GLB binary chunk")?,
+      None => return Err(format!("buffer {} has no URI", i)),
+    };
+    let length = buffer.get("byteLength").as_usize().unwrap_or(data.len());
+    if data.len() < length {
+      return Err(format!("buffer {} is shorter than its byteLength", i));
+    }
+    buffers.push(data);
+  }
+  return Ok(buffers);
+}
+
+fn components(accessor: &Json) -> Result<usize, String> {
+  match accessor.get("type").as_str() {
+    Some("SCALAR") => Ok(1),
+    Some("VEC2") => Ok(2),
+    Some("VEC3") => Ok(3),
+    Some("VEC4") => Ok(4),
+    Some("MAT2") => Ok(4),
+    Some("MAT3") => Ok(9),
+    Some("MAT4") => Ok(16),
+    _ => Err("an accessor has an unknown type".to_string()),
+  }
+}
+
+//Reads all the numbers of an accessor, normalized integers come back between 0 and 1 (or -1 and 1).
+//Numbers are f64 so 32 bit indices stay exact.
+fn read_accessor(gltf: &Json, buffers: &Vec<Vec<u8>>, index: usize) -> Result<Vec<f64>, String> {
+  let accessor = gltf.get("accessors").at(index);
+  let count = accessor.get("count").as_usize().ok_or(format!("accessor {} has no count", index))?;
+  let size = components(accessor)?;
+  let kind = accessor.get("componentType").as_usize().unwrap_or(0);
+  let (width, scale): (usize, f64) = match kind {
+    5120 => (1, 127.0),
+    5121 => (1, 255.0),
+    5122 => (2, 32767.0),
+    5123 => (2, 65535.0),
+    5125 => (4, 1.0),
+    5126 => (4, 1.0),
+    _ => return Err(format!("accessor {} has an unknown component type {}", index, kind)),
+  };
+  let normalized = accessor.get("normalized").as_bool().unwrap_or(false);
+  if !accessor.get("sparse").is_null() {
+    eprintln!("Sparse glTF accessors aren't supported, accessor {} uses its dense values only.", index);
+  }
+
+  //An accessor without a buffer view is all zeros.
+  let too_large = || format!("accessor {} is too large", index);
+  let view = match accessor.get("bufferView").as_usize() {
+    Some(view) => gltf.get("bufferViews").at(view),
+    None => {
+      let length = count.checked_mul(size).filter(|&l| l <= MAX_EMPTY_ACCESSOR).ok_or_else(too_large)?;
+      return Ok(vec![0.0; length]);
+    },
+  };
+  let buffer = buffers.get(view.get("buffer").as_usize().unwrap_or(usize::MAX)).ok_or(format!("accessor {} uses a buffer that doesn't exist", index))?;
+  let start = view.get("byteOffset").as_usize().unwrap_or(0).checked_add(accessor.get("byteOffset").as_usize().unwrap_or(0)).ok_or_else(too_large)?;
+  let stride = view.get("byteStride").as_usize().unwrap_or(width*size);
+  //Elements can't overlap, otherwise a tiny buffer could claim any count.
+  if count > 1 && stride < width*size {
+    return Err(format!("accessor {} has a stride smaller than its elements", index));
+  }
+  if count > 0 {
+    let end = (count - 1).checked_mul(stride).and_then(|e| e.checked_add(width*size)).and_then(|e| e.checked_add(start)).ok_or_else(too_large)?;
+    if end > buffer.len() {
+      return Err(format!("accessor {} reads past the end of its buffer", index));
+    }
+  }
+
+  //The count comes from the file, the values grow as they're read instead of being reserved up front.
+  let mut values = Vec::new();
+  for element in 0..count {
+    for component in 0..size {
+      let at = start + element*stride + component*width;
+      let b = &buffer[at..at + width];
+      let value = match kind {
+        5120 => b[0] as i8 as f64,
+        5121 => b[0] as f64,
+        5122 => i16::from_le_bytes([b[0], b[1]]) as f64,
+        5123 => u16::from_le_bytes([b[0], b[1]]) as f64,
+        5125 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+      };
+      values.push(if normalized {(value/scale).max(-1.0)} else {value});
+    }
+  }
+  return Ok(values);
+}
+
+fn base64(text: &str) -> Option<Vec<u8>> {
+  let mut bytes = Vec::with_capacity(text.len()*3/4);
+  let mut bits: u32 = 0;
+  let mut count = 0;
+  for c in text.bytes() {
+    let value = match c {
+      b'A'..=b'Z' => c - b'A',
+      b'a'..=b'z' => c - b'a' + 26,
+      b'0'..=b'9' => c - b'0' + 52,
+      b'+' | b'-' => 62,
+      b'/' | b'_' => 63,
+      b'=' => break,
+      b' ' | b'\n' | b'\r' | b'\t' => continue,
+      _ => return None,
+    };
+    bits = (bits << 6) | value as u32;
+    count += 6;
+    if count >= 8 {
+      count -= 8;
+      bytes.push((bits >> count) as u8);
+    }
+  }
+  Some(bytes)
+}
+
+//URIs can have escaped characters like %20 for spaces.
+fn percent_decode(uri: &str) -> String {
+  let bytes = uri.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    if bytes[i] == b'%' && i + 2 < bytes.len() {
+      if let Some(byte) = std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|h| u8::from_str_radix(h, 16).ok()) {
+        decoded.push(byte);
+        i += 3;
+        continue;
+      }
+    }
+    decoded.push(bytes[i]);
+    i += 1;
+  }
+  String::from_utf8_lossy(&decoded).to_string()
+}
@@ -0,0 +1,130 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Vector2;
+use definitions::Vector4;
+use definitions::Matrix3;
+use definitions::Material;
+use definitions::Ray;
+
+//Import the mesh loading from model.rs
+use model::{MeshData, load_mesh};
+
+//Import the acceleration structure from bvh.rs
+use bvh::{Aabb, Bvh};
+
+//Import the triangle intersection from intersect.rs
+use intersect::triangle_intersect;
+
+//Import the binary mesh cache from meshcache.rs
+use meshcache::{read_cache, write_cache};
+
+use std::io;
+use std::sync::Arc;
+
+//A mesh loaded once and shared by everything that uses it (models and instances), with a BVH over its triangles.
+#[derive(Debug)]
+pub struct MeshAsset{
+  pub mesh: MeshData,
+  pub bounds: Aabb,
+  pub bvh: Bvh,
+}
+
+impl MeshAsset{
+
+  //With the cache on, a cache file next to the mesh is used when it's still up to date, and written when it isn't.
+  pub fn load(filename: &str, groups: &Vec<String>, cache: bool) -> Self {
+    if !cache {
+      return MeshAsset::new(load_mesh(filename, groups));
+    }
+    match read_cache(filename, groups) {
+      Ok(asset) => return asset,
+      Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+      Err(e) => eprintln!("Rebuilding the mesh cache of {} ({}).", filename, e),
+    }
+    let asset = MeshAsset::new(load_mesh(filename, groups));
+    if !asset.mesh.faces.is_empty() {
+      if let Err(e) = write_cache(filename, groups, &asset) {
+        eprintln!("Failed to write the mesh cache of {}: {}", filename, e);
+      }
+    }
+    return asset;
+  }
+
+  pub fn new(mesh: MeshData) -> Self {
+    let mut mesh = mesh;
+    if mesh.tangents.is_empty() {
+      mesh.compute_tangents();
+    }
+    let triangles: Vec<Aabb> = mesh.faces.iter().map(|f| {
+      let mut b = Aabb::empty();
+      b.grow(mesh.verts[f.x as usize]);
+      b.grow(mesh.verts[f.y as usize]);
+      b.grow(mesh.verts[f.z as usize]);
+      b
+    }).collect();
+    let mut bounds = Aabb::empty();
+    for b in &triangles {
+      bounds.merge(b);
+    }
+    let bvh = Bvh::build(&triangles);
+    MeshAsset {mesh, bounds, bvh}
+  }
+
+  //The closest triangle hit in the asset's own space.
+  pub fn intersect(&self, ray: Ray, material: &Material) -> Option<(f32, Vector3, Vector2, Option<Vector4>, Material)> {
+    let mut closest: Option<((f32, Vector3, Vector2), usize)> = None;
+    let zero = Vector3::new(0.0, 0.0, 0.0);
+    let mesh = &self.mesh;
+    self.bvh.traverse(ray, f32::MAX, |i| {
+      let face = mesh.faces[i];
+      let hit = triangle_intersect(ray, mesh.verts[face.x as usize], mesh.verts[face.y as usize], mesh.verts[face.z as usize], zero)?;
+      if closest.map_or(true, |c| hit.0 < (c.0).0) {
+        closest = Some((hit, i));
+      }
+      Some(hit.0)
+    });
+    return closest.map(|(hit, face)| mesh.surface(face, hit, material));
+  }
+
+}
+
+//One placement of a shared mesh, only the transform and material are stored per instance.
+#[derive(Debug, Clone)]
+pub struct Instance{
+  pub asset: Arc<MeshAsset>,
+  pub transform: Vector3,
+  pub rotation: Matrix3,
+  pub scale: f32,
+  pub material: Material,
+  pub id: u32,
+}
+
+impl Instance{
+
+  pub fn new(asset: Arc<MeshAsset>, transform: Vector3, rotation: Matrix3, scale: f32, material: Material, id: u32) -> Self {
+    Instance {asset, transform, rotation, scale, material, id}
+  }
+
+  //The box around the instance in the world, for the BVH over all instances.
+  pub fn bounds(&self) -> Aabb {
+    let mut bounds = Aabb::empty();
+    for corner in self.asset.bounds.corners().iter() {
+      bounds.grow(self.rotation.apply(*corner*self.scale) + self.transform);
+    }
+    return bounds;
+  }
+
+  //The ray is moved into the asset's space instead of moving the mesh, the direction isn't normalized again so distances stay the same.
+  pub fn intersect(&self, ray: Ray) -> Option<(f32, Vector3, Vector2, Option<Vector4>, Material)> {
+    let inverse = self.rotation.transpose();
+    let local = Ray::new(inverse.apply(ray.origin - self.transform)*(1.0/self.scale), inverse.apply(ray.direction)*(1.0/self.scale), ray.time);
+    return self.asset.intersect(local, &self.material).map(|(t, n, uv, tangent, material)| {
+      let tangent = tangent.map(|v| {
+        let along = self.rotation.apply(Vector3::new(v.x, v.y, v.z));
+        Vector4::new(along.x, along.y, along.z, v.a)
+      });
+      (t, self.rotation.apply(n).normalize(), uv, tangent, material)
+    });
+  }
+
+}
@@ -3,16 +3,72 @@ use std::path::Path;
 use std::io;
 use std::fs::File;
 use std::io::BufRead;
+use std::sync::Arc;
+
+//Import the CSG objects from csg.rs
+use csg::{Csg, CsgNode, CsgOperation};
+
+//Import the signed distance fields from sdf.rs
+use sdf::{Sdf, SdfNode};
 
 //Import all of our structs from definitions.rs
 use definitions::Vector4;
 use definitions::Vector3;
 use definitions::Light;
 use definitions::Sphere;
+use definitions::Plane;
+use definitions::Cuboid;
+use definitions::Cylinder;
+use definitions::Cone;
+use definitions::Disk;
+use definitions::Torus;
+use definitions::Shape;
+use definitions::Matrix3;
 use definitions::Material;
+use definitions::Camera;
+use definitions::Scene;
+use definitions::Settings;
+use definitions::Region;
+
+//Import the tone mapping operators from tonemap.rs
+use tonemap::ToneMap;
+
+//Import the OpenEXR compression modes from exr.rs
+use exr::ExrCompression;
+
+//Import the render passes from passes.rs
+use passes::Pass;
+
+//Import our keyframe types from animation.rs
+use animation::Animation;
+use animation::Keyframe;
+use animation::Target;
+use animation::Property;
+use animation::Interpolation;
 
 //Import our model struct from model.rs
-use model::Model;
+use model::{Model, load_mesh};
+
+//Import the shared meshes and their instances from instance.rs
+use instance::{Instance, MeshAsset};
+
+//Import the surface textures from texture.rs
+use texture::Texture;
+
+//Import the procedural textures from procedural.rs
+use procedural::{Pattern, PatternNode, Space};
+
+//Import the subdivision surfaces from subdivision.rs
+use subdivision::{Scheme, load_subdivided};
+
+//Import the glTF scene importer from gltf.rs
+use gltf::load_gltf;
+
+//Import the acceleration structure from bvh.rs
+use bvh::Bvh;
+
+//Import the fog and volumes from media.rs
+use media::{Media, Medium, Volume, VolumeShape};
 
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
 where P: AsRef<Path>, {
@@ -20,33 +76,500 @@ where P: AsRef<Path>, {
     Ok(io::BufReader::new(file).lines())
 }
 
-pub fn interpreter(input: String) -> (Vec<Light>, Vec<Sphere>, Vec<Model>, Vector3, i32, usize, usize, i32){
+//Reads a medium as density, color and an optional anisotropy.
+fn parse_medium(line_split: &Vec<&str>, start: usize) -> Medium{
+  let color = Vector3::new(line_split[start + 1].parse().unwrap(), line_split[start + 2].parse().unwrap(), line_split[start + 3].parse().unwrap());
+  return Medium::new(line_split[start].parse().unwrap(), color, line_split.get(start + 4).map_or(0.0, |g| g.parse().unwrap()));
+}
+
+//Reads an optional end position for moving objects, objects without one stay still.
+fn parse_transform_end(line_split: &Vec<&str>, start: usize, transform: Vector3) -> Vector3{
+  if line_split.len() >= start + 3 {
+    return Vector3::new(line_split[start].parse().unwrap(), line_split[start + 1].parse().unwrap(), line_split[start + 2].parse().unwrap());
+  }
+  return transform;
+}
+
+//Objects are numbered from 1 in the order they appear in the script, 0 is the background.
+fn object_id(scene: &Scene) -> u32{
+  return (scene.spheres.len() + scene.planes.len() + scene.boxes.len() + scene.cylinders.len() + scene.cones.len() + scene.disks.len() + scene.tori.len() + scene.meshes.len() + scene.csg.len() + scene.sdfs.len() + scene.instances.len()) as u32 + 1;
+}
+
+//Adds a primitive to the scene, or to the CSG block it is in.
+fn add_shape(scene: &mut Scene, csg_stack: &mut Vec<(CsgOperation, Vec<CsgNode>)>, shape: Shape){
+  if let Some(block) = csg_stack.last_mut() {
+    block.1.push(CsgNode::Leaf(shape));
+    return;
+  }
+  match shape {
+    Shape::Sphere(s) => scene.spheres.push(s),
+    Shape::Plane(p) => scene.planes.push(p),
+    Shape::Cuboid(b) => scene.boxes.push(b),
+    Shape::Cylinder(c) => scene.cylinders.push(c),
+    Shape::Cone(c) => scene.cones.push(c),
+    Shape::Disk(d) => scene.disks.push(d),
+    Shape::Torus(t) => scene.tori.push(t),
+    Shape::Mesh(m) => scene.meshes.push(m),
+  }
+}
+
+//Adds the meshes, materials, lights and (with "camera" at the end) the camera of a glTF file to the scene.
+//gl file [x y z [scale]] [camera]
+fn import_gltf(line_split: &Vec<&str>, scene: &mut Scene, settings: &mut Settings, materials: &mut HashMap<String, Material>, last_material_id: &mut u32, in_csg: bool){
+  let numbers: Vec<f32> = line_split[2..].iter().filter_map(|n| n.parse().ok()).collect();
+  let offset = if numbers.len() >= 3 {Vector3::new(numbers[0], numbers[1], numbers[2])} else {Vector3::new(0.0, 0.0, 0.0)};
+  let scale = numbers.get(3).cloned().unwrap_or(1.0);
+  let imported = match load_gltf(line_split[1], offset, scale) {
+    Ok(imported) => imported,
+    Err(e) => {
+      eprintln!("Failed to import {}: {}.", line_split[1], e);
+      return;
+    }
+  };
+  if in_csg {
+    eprintln!("glTF scenes can't be used in CSG blocks, adding it to the scene.");
+  }
+  settings.assets.push(line_split[1].to_string());
+  settings.assets.extend(imported.files);
+  //The materials are named after the file so the script can use them too, like "car:Paint".
+  let prefix = Path::new(line_split[1]).file_stem().and_then(|s| s.to_str()).unwrap_or("gltf");
+  let mut ids: Vec<Material> = Vec::new();
+  for (name, material) in imported.materials {
+    let mut material = material;
+    *last_material_id += 1;
+    material.id = *last_material_id;
+    materials.insert(format!("{}:{}", prefix, name), material.clone());
+    ids.push(material);
+  }
+  let origin = Vector3::new(0.0, 0.0, 0.0);
+  for (mesh, material) in imported.meshes {
+    let id = object_id(scene);
+    scene.meshes.push(Model::from_mesh(mesh, origin, origin, ids[material].clone(), id));
+  }
+  scene.lights.extend(imported.lights);
+  if let Some(camera) = imported.camera {
+    if line_split.last() == Some(&"camera") {
+      scene.camera = Camera::new(camera.transform, camera.target, camera.fov, scene.camera.shutter_open, scene.camera.shutter_close);
+    }
+  }
+}
+
+//What can be given with key=value on a mesh line.
+struct MeshOptions{
+  groups: Vec<String>,
+  scheme: Scheme,
+  levels: u32,
+  //A height map and how far white moves the surface.
+  displacement: Option<(String, f32)>,
+}
+
+//Takes the options of a mesh line out of it, "group=Roof,Walls" only loads those objects or groups of an OBJ file.
+//"subdivide=2 scheme=loop" smooths the mesh at load time, "displace=bricks.pfm depth=0.05" then moves its surface by a height map.
+fn mesh_options<'a>(line_split: &Vec<&'a str>) -> (Vec<&'a str>, MeshOptions){
+  let mut options = MeshOptions {groups: Vec::new(), scheme: Scheme::Auto, levels: 0, displacement: None};
+  let mut depth = 0.1;
+  let mut rest: Vec<&str> = Vec::new();
+  for token in line_split {
+    match token.split_once('=') {
+      Some(("group", names)) => options.groups.extend(names.split(',').filter(|n| !n.is_empty()).map(|n| n.to_string())),
+      Some(("subdivide", l)) => match l.parse::<u32>() {
+        //Every level has four times the faces, a quad at level 6 is already 4096 of them.
+        Ok(levels) if levels > 6 => {
+          eprintln!("Meshes can be subdivided at most 6 times, using 6 instead of {}.", levels);
+          options.levels = 6;
+        },
+        Ok(levels) => options.levels = levels,
+        Err(_) => eprintln!("Can't read the subdivision level '{}', the mesh isn't subdivided.", l),
+      },
+      Some(("scheme", name)) => match Scheme::from_name(name) {
+        Some(scheme) => options.scheme = scheme,
+        None => eprintln!("Unknown subdivision scheme '{}'.", name),
+      },
+      Some(("displace", file)) => options.displacement = Some((file.to_string(), 0.0)),
+      Some(("depth", d)) => match d.parse() {
+        Ok(d) => depth = d,
+        Err(_) => eprintln!("Can't read the displacement depth '{}', using {}.", d, depth),
+      },
+      Some((key, _)) => eprintln!("Unknown mesh option '{}'.", key),
+      None => rest.push(token),
+    }
+  }
+  if let Some(displacement) = options.displacement.as_mut() {
+    displacement.1 = depth;
+  }
+  return (rest, options);
+}
+
+//Loads a mesh with its options. Subdivided and displaced meshes are made again every time instead of going through the cache.
+//The files it reads are added to assets.
+fn load_asset(filename: &str, options: &MeshOptions, cache: bool, textures: &mut HashMap<String, Arc<Texture>>, assets: &mut Vec<String>) -> MeshAsset{
+  assets.push(filename.to_string());
+  if options.levels == 0 && options.displacement.is_none() {
+    return MeshAsset::load(filename, &options.groups, cache);
+  }
+  let mut mesh = if options.levels > 0 {load_subdivided(filename, &options.groups, options.scheme, options.levels)} else {load_mesh(filename, &options.groups)};
+  if let Some((file, depth)) = &options.displacement {
+    if let Some(map) = load_texture(file, textures, assets) {
+      if let Err(e) = mesh.displace(&map, *depth) {
+        eprintln!("Failed to displace {}: {}.", filename, e);
+      }
+    }
+  }
+  return MeshAsset::new(mesh);
+}
+
+//Textures are read once however many materials use them.
+fn load_texture(filename: &str, textures: &mut HashMap<String, Arc<Texture>>, assets: &mut Vec<String>) -> Option<Arc<Texture>>{
+  if let Some(texture) = textures.get(filename) {
+    return Some(texture.clone());
+  }
+  assets.push(filename.to_string());
+  match Texture::load(filename) {
+    Ok(texture) => {
+      let texture = Arc::new(texture);
+      textures.insert(filename.to_string(), texture.clone());
+      return Some(texture);
+    },
+    Err(e) => {
+      eprintln!("Failed to load the texture {}: {}.", filename, e);
+      return None;
+    }
+  }
+}
+
+//Reads the optional rotation in degrees (x y z) at the end of a primitive's line.
+fn parse_rotation(line_split: &Vec<&str>, start: usize) -> Matrix3{
+  if line_split.len() > start + 2 {
+    return Matrix3::rotation(Vector3::new(line_split[start].parse().unwrap(), line_split[start + 1].parse().unwrap(), line_split[start + 2].parse().unwrap()));
+  }
+  return Matrix3::identity();
+}
+
+//Reads a keyframe line: kf target [index] property frame values... [linear|bezier]
+fn parse_keyframe(line_split: &Vec<&str>, animation: &mut Animation){
+  let (target, mut i) = match line_split[1] {
+    "camera" => (Target::Camera, 2),
+    "light" => (Target::Light(line_split[2].parse().unwrap()), 3),
+    "sphere" => (Target::Sphere(line_split[2].parse().unwrap()), 3),
+    "mesh" => (Target::Mesh(line_split[2].parse().unwrap()), 3),
+    other => {
+      eprintln!("Unknown keyframe target '{}'.", other);
+      return;
+    }
+  };
+  let property = match Property::from_name(line_split[i]) {
+    Some(property) => property,
+    None => {
+      eprintln!("Unknown keyframe property '{}'.", line_split[i]);
+      return;
+    }
+  };
+  let frame: f32 = line_split[i + 1].parse().unwrap();
+  i += 2;
+  let mut values: Vec<f32> = Vec::new();
+  for _ in 0..property.size() {
+    values.push(line_split[i].parse().unwrap());
+    i += 1;
+  }
+  values.resize(3, 0.0);
+  let interpolation = match line_split.get(i) {
+    Some(&"bezier") => Interpolation::Bezier,
+    _ => Interpolation::Linear,
+  };
+  animation.add_key(target, property, Keyframe{frame, value: Vector3::new(values[0], values[1], values[2]), interpolation});
+}
+
+//Turns "rg pixel x0 y0 x1 y1" or "rg border left top right bottom" (0 to 1 from the top left) into pixels inside the frame.
+fn parse_region(border: bool, r: [f32; 4], fwidth: usize, fheight: usize) -> Region{
+  let (sx, sy) = if border {(fwidth as f32, fheight as f32)} else {(1.0, 1.0)};
+  let clamp = |v: f32, max: usize| usize::min(f32::max(v.round(), 0.0) as usize, max);
+  let x0 = clamp(r[0]*sx, fwidth);
+  let y0 = clamp(r[1]*sy, fheight);
+  let x1 = clamp(r[2]*sx, fwidth);
+  let y1 = clamp(r[3]*sy, fheight);
+  if x1 <= x0 || y1 <= y0 {
+    eprintln!("The render region is empty, rendering the whole frame instead.");
+    return Region::new(0, 0, fwidth, fheight);
+  }
+  return Region::new(x0, y0, x1, y1);
+}
+
+pub fn interpreter(input: String) -> (Scene, Settings, Animation){
   let mut materials: HashMap<String, Material> = HashMap::new();
-  let mut lights: Vec<Light> = Vec::new();
-  let mut spheres: Vec<Sphere> = Vec::new();
-  let mut meshes: Vec<Model> = Vec::new();
-  let mut background_color: Vector3 = Vector3{x: 1.0, y: 1.0, z: 1.0};
-  let mut path_depth: i32 = 5;
-  let mut fheight: usize = 1280;
-  let mut fwidth: usize = 720;
-  let mut anti_alias: i32 = 1;
+  //Material IDs only ever go up, so redefining a name still gives the new material its own ID.
+  let mut last_material_id: u32 = 0;
+  //Meshes loaded with 'mh', every instance of one shares its triangles.
+  let mut assets: HashMap<String, Arc<MeshAsset>> = HashMap::new();
+  //Whether the meshes that follow are loaded through binary cache files next to them.
+  let mut mesh_cache = false;
+  //Normal, bump and displacement maps by file name.
+  let mut textures: HashMap<String, Arc<Texture>> = HashMap::new();
+  //Procedural patterns by the name they were given with 'tx', shared by every material that uses them like textures are.
+  let mut patterns: HashMap<String, Arc<Pattern>> = HashMap::new();
+  let mut scene = Scene{
+    camera: Camera::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0), 1.0, 0.0, 0.0),
+    lights: Vec::new(),
+    spheres: Vec::new(),
+    planes: Vec::new(),
+    boxes: Vec::new(),
+    cylinders: Vec::new(),
+    cones: Vec::new(),
+    disks: Vec::new(),
+    tori: Vec::new(),
+    csg: Vec::new(),
+    sdfs: Vec::new(),
+    meshes: Vec::new(),
+    instances: Vec::new(),
+    instance_bvh: Bvh::build(&Vec::new()),
+    media: Media::new(),
+    background_color: Vector3{x: 1.0, y: 1.0, z: 1.0},
+  };
+  let mut settings = Settings{
+    path_depth: 5,
+    fheight: 1280,
+    fwidth: 720,
+    anti_alias: 1,
+    samples: 1,
+    frame_start: 1,
+    frame_end: 1,
+    outputs: Vec::new(),
+    exposure: 0.0,
+    tone_map: ToneMap::Clamp,
+    dither: false,
+    exr_compression: ExrCompression::Rle,
+    exr_half: true,
+    passes: Vec::new(),
+    denoise_strength: 0.0,
+    denoise_iterations: 5,
+    adaptive_samples: 0,
+    adaptive_threshold: 0.01,
+    progressive: false,
+    snapshot_interval: 0.0,
+    seed: 0,
+    checkpoint: String::new(),
+    checkpoint_interval: 0.0,
+    assets: Vec::new(),
+    region: Region::new(0, 0, 0, 0),
+    crop: false,
+  };
+  //The region can be given before the resolution, so it is only turned into pixels at the end.
+  let mut region: Option<(bool, [f32; 4])> = None;
+  let mut animation = Animation::new();
+  //CSG blocks that are still open, primitives go into the innermost one instead of the scene.
+  let mut csg_stack: Vec<(CsgOperation, Vec<CsgNode>)> = Vec::new();
+  //Sphere tracing settings for the SDF objects that follow: maximum steps, epsilon and how far a ray may go.
+  let mut sdf_tracing: (u32, f32, f32) = (256, 0.0005, 1000.0);
   if let Ok(lines) = read_lines(input) {
     for line in lines.map_while(Result::ok) {
       let line_split: Vec<&str> = line.split_whitespace().collect();
       //This is super messy, I'll look into making it way cleaner soon...
-      match line.chars().take(2).collect::<String>().as_str() {
-        "h " => fheight = line_split[1].parse().unwrap(),
-        "w " => fwidth = line_split[1].parse().unwrap(),
-        "r " => path_depth = line_split[1].parse().unwrap(),
-        "aa" => anti_alias = line_split[1].parse().unwrap(),
-        "bg" => background_color = Vector3{x: line_split[1].parse().unwrap(), y: line_split[2].parse().unwrap(), z: line_split[3].parse().unwrap()},
-        "mt" => {materials.insert(line_split[1].to_string(), Material::new(Vector3::new(line_split[2].parse().unwrap(), line_split[3].parse().unwrap(), line_split[4].parse().unwrap()), Vector4::new(line_split[5].parse().unwrap(), line_split[6].parse().unwrap(), line_split[7].parse().unwrap(), line_split[8].parse().unwrap()), line_split[9].parse().unwrap(), line_split[10].parse().unwrap()));},
-        "l " => lights.push(Light::new(Vector3::new(line_split[1].parse().unwrap(), line_split[2].parse().unwrap(), line_split[3].parse().unwrap()), line_split[4].parse().unwrap())),
-        "sp" => spheres.push(Sphere::new(Vector3::new(line_split[1].parse().unwrap(), line_split[2].parse().unwrap(), line_split[3].parse().unwrap()), line_split[4].parse().unwrap(), materials[line_split[5]])),
-        "ms" => meshes.push(Model::new(line_split[1], Vector3::new(line_split[2].parse().unwrap(), line_split[3].parse().unwrap(), line_split[4].parse().unwrap()), materials[line_split[5]])),
+      match line.trim_start().chars().take(2).collect::<String>().as_str() {
+        "h " => settings.fheight = line_split[1].parse().unwrap(),
+        "w " => settings.fwidth = line_split[1].parse().unwrap(),
+        "r " => settings.path_depth = line_split[1].parse().unwrap(),
+        "aa" => settings.anti_alias = line_split[1].parse().unwrap(),
+        "ss" => settings.samples = line_split[1].parse().unwrap(),
+        "fr" => {settings.frame_start = line_split[1].parse().unwrap(); settings.frame_end = line_split[2].parse().unwrap();},
+        "ou" => settings.outputs.push(line_split[1].to_string()),
+        "ex" => settings.exposure = line_split[1].parse().unwrap(),
+        "tm" => match ToneMap::from_name(line_split[1], line_split.get(2).map(|w| w.parse().unwrap())) {
+          Some(tone_map) => settings.tone_map = tone_map,
+          None => eprintln!("Unknown tone mapping operator '{}'.", line_split[1]),
+        },
+        "dt" => settings.dither = line_split[1] == "1",
+        "xr" => {settings.exr_compression = if line_split.get(1) == Some(&"none") {ExrCompression::None} else {ExrCompression::Rle}; settings.exr_half = line_split.get(2) != Some(&"float");},
+        "cm" => scene.camera = Camera::new(Vector3::new(line_split[1].parse().unwrap(), line_split[2].parse().unwrap(), line_split[3].parse().unwrap()), Vector3::new(line_split[4].parse().unwrap(), line_split[5].parse().unwrap(), line_split[6].parse().unwrap()), line_split[7].parse().unwrap(), scene.camera.shutter_open, scene.camera.shutter_close),
+        "sh" => {scene.camera.shutter_open = line_split[1].parse().unwrap(); scene.camera.shutter_close = line_split[2].parse().unwrap();},
+        "bg" => scene.background_color = Vector3{x: line_split[1].parse().unwrap(), y: line_split[2].parse().unwrap(), z: line_split[3].parse().unwrap()},
+        "fg" => scene.media.fog = Some(parse_medium(&line_split, 1)),
+        "vo" => match line_split[1] {
+          "sphere" => {
+            let center = Vector3::new(line_split[2].parse().unwrap(), line_split[3].parse().unwrap(), line_split[4].parse().unwrap());
+            let shape = VolumeShape::Sphere(center, line_split[5].parse().unwrap());
+            scene.media.volumes.push(Volume {shape, medium: parse_medium(&line_split, 6)});
+          },
+          "mesh" => {
+            let (line_split, options) = mesh_options(&line_split);
+            let offset = Vector3::new(line_split[3].parse().unwrap(), line_split[4].parse().unwrap(), line_split[5].parse().unwrap());
+            let shape = VolumeShape::Mesh(Arc::new(load_asset(line_split[2], &options, mesh_cache, &mut textures, &mut settings.assets)), offset);
+            scene.media.volumes.push(Volume {shape, medium: parse_medium(&line_split, 6)});
+          },
+          shape => eprintln!("Unknown volume shape '{}', use sphere or mesh.", shape),
+        },
+        "vs" => scene.media.steps = line_split[1].parse::<u32>().unwrap().max(1),
+        "pa" => for name in &line_split[1..] {
+          match Pass::from_name(name) {
+            Some(pass) => if !settings.passes.contains(&pass) {settings.passes.push(pass)},
+            None => eprintln!("Unknown render pass '{}'.", name),
+          }
+        },
+        "ad" => {settings.adaptive_samples = line_split[1].parse().unwrap(); settings.adaptive_threshold = line_split[2].parse().unwrap();},
+        "pg" => {settings.progressive = true; settings.snapshot_interval = line_split[1].parse().unwrap();},
+        "sd" => settings.seed = line_split[1].parse().unwrap(),
+        "cp" => {settings.checkpoint = line_split[1].to_string(); settings.checkpoint_interval = line_split[2].parse().unwrap();},
+        "rg" => {
+          region = Some((line_split[1] == "border", [line_split[2].parse().unwrap(), line_split[3].parse().unwrap(), line_split[4].parse().unwrap(), line_split[5].parse().unwrap()]));
+          settings.crop = line_split.get(6) == Some(&"crop");
+        },
+        "dn" => {
+          settings.denoise_strength = line_split[1].parse().unwrap();
+          if line_split.len() > 2 {
+            //Every iteration reaches twice as far, after 16 the filter already spans 65536 pixels.
+            let iterations: i32 = line_split[2].parse().unwrap();
+            if iterations < 0 || iterations > 16 {
+              eprintln!("Denoising takes 0 to 16 iterations, using {}.", iterations.max(0).min(16));
+            }
+            settings.denoise_iterations = iterations.max(0).min(16);
+          }
+        },
+        "mt" => {last_material_id += 1; let id = last_material_id; materials.insert(line_split[1].to_string(), Material::new(Vector3::new(line_split[2].parse().unwrap(), line_split[3].parse().unwrap(), line_split[4].parse().unwrap()), Vector4::new(line_split[5].parse().unwrap(), line_split[6].parse().unwrap(), line_split[7].parse().unwrap(), line_split[8].parse().unwrap()), line_split[9].parse().unwrap(), line_split[10].parse().unwrap(), id));},
+        "nm" | "bm" => match (materials.get_mut(line_split[1]), load_texture(line_split[2], &mut textures, &mut settings.assets)) {
+          (Some(material), Some(texture)) => {
+            let amount = line_split.get(3).map(|a| a.parse().unwrap());
+            if line_split[0] == "nm" {
+              material.normal_map = Some(texture);
+              material.normal_strength = amount.unwrap_or(1.0);
+            }
+            else {
+              material.bump_map = Some(texture);
+              material.bump_scale = amount.unwrap_or(0.01);
+            }
+          },
+          (None, _) => eprintln!("Unknown material '{}', define it with 'mt' first.", line_split[1]),
+          _ => (),
+        },
+        "tx" => {
+          let space = match line_split.get(2) {
+            Some(&"world") => Some(Space::World),
+            Some(&"object") => Some(Space::Object),
+            Some(&"uv") => Some(Space::Uv),
+            _ => None,
+          };
+          let start = if space.is_some() {3} else {2};
+          match PatternNode::parse(&mut line_split[start..].iter().cloned()) {
+            Ok(root) => {
+              patterns.insert(line_split[1].to_string(), Arc::new(Pattern {root, space: space.unwrap_or(Space::Object)}));
+            },
+            Err(e) => eprintln!("Failed to read the pattern {}: {}.", line_split[1], e),
+          }
+        },
+        "mp" => match (materials.get_mut(line_split[1]), patterns.get(line_split[3])) {
+          (Some(material), Some(pattern)) => match line_split[2] {
+            "color" => material.color_pattern = Some(pattern.clone()),
+            "roughness" => material.roughness_pattern = Some(pattern.clone()),
+            "bump" => {
+              material.bump_pattern = Some(pattern.clone());
+              material.pattern_bump_scale = line_split.get(4).map_or(0.05, |s| s.parse().unwrap());
+            },
+            slot => eprintln!("Unknown pattern slot '{}', use color, roughness or bump.", slot),
+          },
+          (None, _) => eprintln!("Unknown material '{}', define it with 'mt' first.", line_split[1]),
+          (_, None) => eprintln!("Unknown pattern '{}', define it with 'tx' first.", line_split[3]),
+        },
+        "l " => scene.lights.push(Light::new(Vector3::new(line_split[1].parse().unwrap(), line_split[2].parse().unwrap(), line_split[3].parse().unwrap()), line_split[4].parse().unwrap())),
+        "sp" => {
+          let transform = Vector3::new(line_split[1].parse().unwrap(), line_split[2].parse().unwrap(), line_split[3].parse().unwrap());
+          let id = object_id(&scene);
+          add_shape(&mut scene, &mut csg_stack, Shape::Sphere(Sphere::new(transform, parse_transform_end(&line_split, 6, transform), line_split[4].parse().unwrap(), materials[line_split[5]].clone(), id)));
+        },
+        "pl" => {
+          let id = object_id(&scene);
+          add_shape(&mut scene, &mut csg_stack, Shape::Plane(Plane::new(Vector3::new(line_split[1].parse().unwrap(), line_split[2].parse().unwrap(), line_split[3].parse().unwrap()), Vector3::new(line_split[4].parse().unwrap(), line_split[5].parse().unwrap(), line_split[6].parse().unwrap()), materials[line_split[7]].clone(), id)));
+        },
+        "bx" => {
+          let rotation = parse_rotation(&line_split, 8);
+          let id = object_id(&scene);
+          add_shape(&mut scene, &mut csg_stack, Shape::Cuboid(Cuboid::new(Vector3::new(line_split[1].parse().unwrap(), line_split[2].parse().unwrap(), line_split[3].parse().unwrap()), Vector3::new(line_split[4].parse().unwrap(), line_split[5].parse().unwrap(), line_split[6].parse().unwrap()), rotation, materials[line_split[7]].clone(), id)));
+        },
+        "cy" => {
+          let transform = Vector3::new(line_split[1].parse().unwrap(), line_split[2].parse().unwrap(), line_split[3].parse().unwrap());
+          let id = object_id(&scene);
+          add_shape(&mut scene, &mut csg_stack, Shape::Cylinder(Cylinder::new(transform, line_split[4].parse().unwrap(), line_split[5].parse().unwrap(), line_split[6] == "1", parse_rotation(&line_split, 8), materials[line_split[7]].clone(), id)));
+        },
+        "co" => {
+          let transform = Vector3::new(line_split[1].parse().unwrap(), line_split[2].parse().unwrap(), line_split[3].parse().unwrap());
+          let id = object_id(&scene);
+          add_shape(&mut scene, &mut csg_stack, Shape::Cone(Cone::new(transform, line_split[4].parse().unwrap(), line_split[5].parse().unwrap(), line_split[6] == "1", parse_rotation(&line_split, 8), materials[line_split[7]].clone(), id)));
+        },
+        "dk" => {
+          let transform = Vector3::new(line_split[1].parse().unwrap(), line_split[2].parse().unwrap(), line_split[3].parse().unwrap());
+          let id = object_id(&scene);
+          add_shape(&mut scene, &mut csg_stack, Shape::Disk(Disk::new(transform, line_split[4].parse().unwrap(), parse_rotation(&line_split, 6), materials[line_split[5]].clone(), id)));
+        },
+        "to" => {
+          let transform = Vector3::new(line_split[1].parse().unwrap(), line_split[2].parse().unwrap(), line_split[3].parse().unwrap());
+          let id = object_id(&scene);
+          add_shape(&mut scene, &mut csg_stack, Shape::Torus(Torus::new(transform, line_split[4].parse().unwrap(), line_split[5].parse().unwrap(), parse_rotation(&line_split, 7), materials[line_split[6]].clone(), id)));
+        },
+        "mc" => mesh_cache = line_split[1] == "1",
+        "ms" => {
+          let (line_split, options) = mesh_options(&line_split);
+          let transform = Vector3::new(line_split[2].parse().unwrap(), line_split[3].parse().unwrap(), line_split[4].parse().unwrap());
+          let asset = Arc::new(load_asset(line_split[1], &options, mesh_cache, &mut textures, &mut settings.assets));
+          let id = object_id(&scene);
+          add_shape(&mut scene, &mut csg_stack, Shape::Mesh(Model::new(asset, transform, parse_transform_end(&line_split, 6, transform), materials[line_split[5]].clone(), id)));
+        },
+        "mh" => {
+          let (line_split, options) = mesh_options(&line_split);
+          assets.insert(line_split[1].to_string(), Arc::new(load_asset(line_split[2], &options, mesh_cache, &mut textures, &mut settings.assets)));
+        },
+        "in" => match assets.get(line_split[1]) {
+          Some(asset) => {
+            if !csg_stack.is_empty() {
+              eprintln!("Instances can't be used in CSG blocks, adding it to the scene.");
+            }
+            let transform = Vector3::new(line_split[2].parse().unwrap(), line_split[3].parse().unwrap(), line_split[4].parse().unwrap());
+            let scale = line_split.get(9).map_or(1.0, |s| s.parse().unwrap());
+            let id = object_id(&scene);
+            scene.instances.push(Instance::new(asset.clone(), transform, parse_rotation(&line_split, 6), scale, materials[line_split[5]].clone(), id));
+          },
+          None => eprintln!("Unknown mesh '{}', load it with 'mh' first.", line_split[1]),
+        },
+        "gl" => import_gltf(&line_split, &mut scene, &mut settings, &mut materials, &mut last_material_id, !csg_stack.is_empty()),
+        "st" => sdf_tracing = (line_split[1].parse().unwrap(), line_split[2].parse().unwrap(), line_split.get(3).map_or(sdf_tracing.2, |d| d.parse().unwrap())),
+        "sf" => match SdfNode::parse(&mut line_split[2..].iter().cloned()) {
+          Ok(root) => {
+            if !csg_stack.is_empty() {
+              eprintln!("SDF objects can't be used in CSG blocks (use the SDF combinators instead), adding it to the scene.");
+            }
+            let id = object_id(&scene);
+            scene.sdfs.push(Sdf::new(root, materials[line_split[1]].clone(), sdf_tracing.0, sdf_tracing.1, sdf_tracing.2, id));
+          },
+          Err(e) => eprintln!("Failed to read the SDF object: {}.", e),
+        },
+        "cs" => match CsgOperation::from_name(line_split[1]) {
+          Some(operation) => csg_stack.push((operation, Vec::new())),
+          None => eprintln!("Unknown CSG operation '{}'.", line_split[1]),
+        },
+        "ce" => match csg_stack.pop() {
+          Some((operation, children)) => {
+            let node = CsgNode::Operation(operation, children);
+            match csg_stack.last_mut() {
+              Some(parent) => parent.1.push(node),
+              None => {
+                let id = object_id(&scene);
+                scene.csg.push(Csg::new(node, id));
+              }
+            }
+          },
+          None => eprintln!("'ce' without a matching 'cs'."),
+        },
+        "kf" => parse_keyframe(&line_split, &mut animation),
         _ => (),
       }
     }
   }
-  return (lights, spheres, meshes, background_color, path_depth, fheight, fwidth, anti_alias);
-}
\ No newline at end of file
+  if !csg_stack.is_empty() {
+    eprintln!("A CSG block ('cs') is missing its 'ce', it won't be rendered.");
+  }
+  scene.instance_bvh = Bvh::build(&scene.instances.iter().map(|i| i.bounds()).collect());
+  animation.validate(&scene);
+  settings.region = match region {
+    Some((border, r)) => parse_region(border, r, settings.fwidth, settings.fheight),
+    None => Region::new(0, 0, settings.fwidth, settings.fheight),
+  };
+  if settings.outputs.is_empty() {
+    settings.outputs.push("./out.ppm".to_string());
+  }
+  return (scene, settings, animation);
+}
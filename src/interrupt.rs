@@ -0,0 +1,29 @@
+//Catches Ctrl+C so a render can stop early and still write out what it has.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+const SIGINT: i32 = 2;
+
+//The C library is linked anyway, so we can ask it directly instead of pulling in a crate.
+extern "C" {
+  fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+  fn _exit(status: i32) -> !;
+}
+
+extern "C" fn on_interrupt(_: i32){
+  //A second Ctrl+C means the user doesn't want to wait for the image to be written.
+  if INTERRUPTED.swap(true, Ordering::SeqCst) {
+    unsafe { _exit(130); }
+  }
+}
+
+pub fn install(){
+  unsafe {
+    signal(SIGINT, on_interrupt);
+  }
+}
+
+pub fn interrupted() -> bool{
+  INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,552 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Vector2;
+use definitions::Vector4;
+use definitions::Sphere;
+use definitions::Plane;
+use definitions::Cuboid;
+use definitions::Cylinder;
+use definitions::Cone;
+use definitions::Disk;
+use definitions::Torus;
+use definitions::Matrix3;
+use definitions::Material;
+use definitions::Shape;
+use definitions::Ray;
+
+//Import our model struct from model.rs
+use model::Model;
+
+use std::f32::consts::PI;
+
+//How far in front of the origin a hit has to be, so rays don't hit the surface they start on.
+const EPSILON: f32 = 0.0001;
+
+//Every intersection returns the distance along the ray, the surface normal and the UV coordinates of the hit.
+//Meshes and other shapes also return their tangent, primitives find it from the hit with their own *_tangent function.
+
+//Checks if a ray hits a sphere (at the sphere's position for the ray's time).
+//The UVs wrap around the equator (u) and go from the bottom to the top (v).
+pub fn sphere_intersect(sphere: &Sphere, ray: Ray) -> Option<(f32, Vector3, Vector2)>{
+  let center = sphere.transform_at(ray.time);
+  let length = center - ray.origin;
+  let projection = length.dot(&ray.direction);
+  let difference_of_squares = (length.dot(&length)) - projection*projection;
+  if difference_of_squares > sphere.radius*sphere.radius{
+    return None;
+  }
+  let temp = (sphere.radius*sphere.radius - difference_of_squares).sqrt();
+  let mut distance = projection - temp;
+  let point1 = projection + temp;
+  if distance < 0.0{
+    distance = point1;
+  }
+  if distance < 0.0{
+    return None;
+  }
+  let normal = (ray.origin + ray.direction*distance - center).normalize();
+  let uv = Vector2::new(0.5 + normal.z.atan2(normal.x)/(2.0*PI), 0.5 + normal.y.max(-1.0).min(1.0).asin()/PI);
+  return Some((distance, normal, uv));
+}
+
+//Checks if a ray hits a triangle (normally in a mesh), the UVs are the barycentric coordinates of the hit.
+pub fn triangle_intersect(ray: Ray, v0: Vector3, v1: Vector3, v2: Vector3, transform: Vector3) -> Option<(f32, Vector3, Vector2)> {
+  let origin = ray.origin;
+  let direction = ray.direction;
+  let v0 = v0 + transform;
+  let v1 = v1 + transform;
+  let v2 = v2 + transform;
+  let edge1 = v1 - v0;
+  let edge2 = v2 - v0;
+  let h = direction.cross(&edge2);
+  let a = edge1.dot(&h);
+
+  if a.abs() < 0.0001{
+    return None;
+  }
+
+  let f = 1.0 / a;
+  let s = origin - v0;
+  let u = f * s.dot(&h);
+
+  if !(0.0..=1.0).contains(&u){
+    return None;
+  }
+
+  let q = s.cross(&edge1);
+  let v = f * direction.dot(&q);
+
+  if v < 0.0 || u + v > 1.0{
+    return None;
+  }
+
+  let t = f * edge2.dot(&q);
+  if t > EPSILON{
+    let normal = edge1.cross(&edge2).normalize();
+    return Some((t, normal, Vector2::new(u, v)));
+  }
+  else{
+    return None;
+  }
+}
+
+//Finds the closest triangle of a mesh through its BVH, the material comes back too as vertex colors change it.
+//The mesh stays where it was loaded and the ray is moved the other way instead.
+pub fn mesh_intersect(model: &Model, ray: Ray) -> Option<(f32, Vector3, Vector2, Option<Vector4>, Material)>{
+  let transform = model.transform_at(ray.time);
+  return model.asset.intersect(Ray::new(ray.origin - transform, ray.direction, ray.time), &model.material);
+}
+
+//Checks any kind of primitive, returning its material with the hit.
+pub fn shape_intersect(shape: &Shape, ray: Ray) -> Option<(f32, Vector3, Vector2, Option<Vector4>, Material)>{
+  let found = match shape{
+    Shape::Sphere(s) => sphere_intersect(s, ray),
+    Shape::Plane(p) => plane_intersect(p, ray),
+    Shape::Cuboid(b) => box_intersect(b, ray),
+    Shape::Cylinder(c) => cylinder_intersect(c, ray),
+    Shape::Cone(c) => cone_intersect(c, ray),
+    Shape::Disk(d) => disk_intersect(d, ray),
+    Shape::Torus(t) => torus_intersect(t, ray),
+    Shape::Mesh(m) => return mesh_intersect(m, ray),
+  };
+  return found.map(|(t, n, uv)| (t, n, uv, shape_tangent(shape, ray.origin + ray.direction*t, n), shape.material().clone()));
+}
+
+//The tangent of any primitive at a hit on it (meshes bring their own).
+fn shape_tangent(shape: &Shape, point: Vector3, normal: Vector3) -> Option<Vector4>{
+  match shape{
+    Shape::Sphere(_) => sphere_tangent(normal),
+    Shape::Plane(p) => plane_tangent(p),
+    Shape::Cuboid(b) => box_tangent(b, normal),
+    Shape::Cylinder(c) => cylinder_tangent(c, normal),
+    Shape::Cone(c) => cone_tangent(c, normal),
+    Shape::Disk(d) => disk_tangent(d, normal),
+    Shape::Torus(t) => torus_tangent(t, point, normal),
+    Shape::Mesh(_) => None,
+  }
+}
+
+//Checks if a ray hits an infinite plane, the UVs are distances in world units along two directions in the plane.
+pub fn plane_intersect(plane: &Plane, ray: Ray) -> Option<(f32, Vector3, Vector2)>{
+  let facing = plane.normal.dot(&ray.direction);
+  if facing.abs() < 1e-6{
+    return None;
+  }
+  let t = (plane.point - ray.origin).dot(&plane.normal)/facing;
+  if t <= EPSILON{
+    return None;
+  }
+  let (tangent, bitangent) = plane_basis(plane.normal);
+  let offset = ray.origin + ray.direction*t - plane.point;
+  return Some((t, plane.normal, Vector2::new(offset.dot(&tangent), offset.dot(&bitangent))));
+}
+
+//Two directions in the plane, picked so the UVs of a ground plane line up with x and z.
+pub fn plane_basis(normal: Vector3) -> (Vector3, Vector3){
+  let axis = if normal.x.abs() > 0.9 {Vector3::new(0.0, 1.0, 0.0)} else {Vector3::new(1.0, 0.0, 0.0)};
+  let tangent = (axis - normal*normal.dot(&axis)).normalize();
+  return (tangent, normal.cross(&tangent));
+}
+
+//Checks if a ray hits a box using the slab method, in the box's own rotated space.
+//Every face gets its own 0 to 1 UVs.
+pub fn box_intersect(cuboid: &Cuboid, ray: Ray) -> Option<(f32, Vector3, Vector2)>{
+  let inverse = cuboid.rotation.transpose();
+  let origin = inverse.apply(ray.origin - cuboid.center());
+  let direction = inverse.apply(ray.direction);
+  let half = (cuboid.max - cuboid.min)*0.5;
+
+  let mut near = f32::MIN;
+  let mut far = f32::MAX;
+  let mut near_axis = 0;
+  let mut far_axis = 0;
+  for i in 0..3{
+    let (o, d, h) = (axis(origin, i), axis(direction, i), axis(half, i));
+    if d.abs() < 1e-9{
+      //Parallel to this pair of faces, so it has to start between them.
+      if o.abs() > h{
+        return None;
+      }
+      continue;
+    }
+    let t1 = (-h - o)/d;
+    let t2 = (h - o)/d;
+    if f32::min(t1, t2) > near{
+      near = f32::min(t1, t2);
+      near_axis = i;
+    }
+    if f32::max(t1, t2) < far{
+      far = f32::max(t1, t2);
+      far_axis = i;
+    }
+  }
+  if near > far || far <= EPSILON{
+    return None;
+  }
+  //Rays starting inside the box leave through the far side.
+  let (t, i) = if near > EPSILON {(near, near_axis)} else {(far, far_axis)};
+  let point = origin + direction*t;
+  let mut local_normal = Vector3::new(0.0, 0.0, 0.0);
+  let side = if axis(point, i) < 0.0 {-1.0} else {1.0};
+  match i{
+    0 => local_normal.x = side,
+    1 => local_normal.y = side,
+    _ => local_normal.z = side,
+  }
+  let (a, b) = ((i + 1) % 3, (i + 2) % 3);
+  let uv = Vector2::new((axis(point, a) + axis(half, a))/(2.0*axis(half, a)), (axis(point, b) + axis(half, b))/(2.0*axis(half, b)));
+  return Some((t, cuboid.rotation.apply(local_normal), uv));
+}
+
+//Checks if a ray hits a cylinder, the side wraps u around the axis and v goes up, the caps are mapped like disks.
+pub fn cylinder_intersect(cylinder: &Cylinder, ray: Ray) -> Option<(f32, Vector3, Vector2)>{
+  let (origin, direction) = local_ray(ray, cylinder.transform, cylinder.rotation);
+  let r = cylinder.radius;
+  let h = cylinder.height;
+  let mut closest: Option<(f32, Vector3, Vector2)> = None;
+
+  let a = direction.x*direction.x + direction.z*direction.z;
+  let b = 2.0*(origin.x*direction.x + origin.z*direction.z);
+  let c = origin.x*origin.x + origin.z*origin.z - r*r;
+  for t in quadratic_roots(a, b, c){
+    let p = origin + direction*t;
+    if t > EPSILON && p.y >= 0.0 && p.y <= h{
+      closest = nearest(closest, (t, Vector3::new(p.x/r, 0.0, p.z/r), Vector2::new(around(p), p.y/h)));
+      break;
+    }
+  }
+  if cylinder.capped{
+    closest = nearest_option(closest, cap_intersect(origin, direction, 0.0, r, -1.0));
+    closest = nearest_option(closest, cap_intersect(origin, direction, h, r, 1.0));
+  }
+  return closest.map(|(t, n, uv)| (t, cylinder.rotation.apply(n), uv));
+}
+
+//Checks if a ray hits a cone, mapped the same way as a cylinder.
+pub fn cone_intersect(cone: &Cone, ray: Ray) -> Option<(f32, Vector3, Vector2)>{
+  let (origin, direction) = local_ray(ray, cone.transform, cone.rotation);
+  let r = cone.radius;
+  let h = cone.height;
+  let mut closest: Option<(f32, Vector3, Vector2)> = None;
+
+  //x^2 + z^2 = (r/h)^2 (h - y)^2
+  let slope = (r/h)*(r/h);
+  let m = h - origin.y;
+  let a = direction.x*direction.x + direction.z*direction.z - slope*direction.y*direction.y;
+  let b = 2.0*(origin.x*direction.x + origin.z*direction.z + slope*m*direction.y);
+  let c = origin.x*origin.x + origin.z*origin.z - slope*m*m;
+  for t in quadratic_roots(a, b, c){
+    let p = origin + direction*t;
+    if t > EPSILON && p.y >= 0.0 && p.y <= h{
+      let normal = Vector3::new(p.x, slope*(h - p.y), p.z).normalize();
+      closest = nearest(closest, (t, normal, Vector2::new(around(p), p.y/h)));
+      break;
+    }
+  }
+  if cone.capped{
+    closest = nearest_option(closest, cap_intersect(origin, direction, 0.0, r, -1.0));
+  }
+  return closest.map(|(t, n, uv)| (t, cone.rotation.apply(n), uv));
+}
+
+//Checks if a ray hits a disk, the UVs go from 0 to 1 across it.
+pub fn disk_intersect(disk: &Disk, ray: Ray) -> Option<(f32, Vector3, Vector2)>{
+  let (origin, direction) = local_ray(ray, disk.transform, disk.rotation);
+  return cap_intersect(origin, direction, 0.0, disk.radius, 1.0).map(|(t, n, uv)| (t, disk.rotation.apply(n), uv));
+}
+
+//Checks if a ray hits a torus by solving the quartic, u goes around the ring and v around the tube.
+pub fn torus_intersect(torus: &Torus, ray: Ray) -> Option<(f32, Vector3, Vector2)>{
+  let (origin, direction) = local_ray(ray, torus.transform, torus.rotation);
+  let major = torus.major_radius as f64;
+  let minor = torus.minor_radius as f64;
+  //Skip everything that misses the bounding sphere, and start the quartic from there to keep the numbers small.
+  let bound = major + minor;
+  let f = origin.dot(&direction) as f64;
+  let distance_squared = origin.dot(&origin) as f64 - f*f;
+  if distance_squared > bound*bound{
+    return None;
+  }
+  let start = f64::max(-f - (bound*bound - distance_squared).sqrt(), 0.0);
+  let (ox, oy, oz) = (origin.x as f64 + direction.x as f64*start, origin.y as f64 + direction.y as f64*start, origin.z as f64 + direction.z as f64*start);
+  let (dx, dy, dz) = (direction.x as f64, direction.y as f64, direction.z as f64);
+
+  //(|p|^2 + R^2 - r^2)^2 = 4R^2 (x^2 + z^2), with p = o + td and |d| = 1.
+  let f = ox*dx + oy*dy + oz*dz;
+  let g = ox*ox + oy*oy + oz*oz + major*major - minor*minor;
+  let four_r2 = 4.0*major*major;
+  let coefficients = [
+    g*g - four_r2*(ox*ox + oz*oz),
+    4.0*f*g - 2.0*four_r2*(ox*dx + oz*dz),
+    4.0*f*f + 2.0*g - four_r2*(dx*dx + dz*dz),
+    4.0*f,
+    1.0,
+  ];
+  let mut t = f64::MAX;
+  for root in solve_quartic(coefficients){
+    //Polish the root, the closed form loses a few digits.
+    let root = newton(&coefficients, root) + start;
+    if root > EPSILON as f64 && root < t{
+      t = root;
+    }
+  }
+  if t == f64::MAX{
+    return None;
+  }
+  let t = t as f32;
+  let p = origin + direction*t;
+  let ring = Vector3::new(p.x, 0.0, p.z).normalize();
+  let tube = p - ring*torus.major_radius;
+  let normal = tube.normalize();
+  let v = 0.5 + tube.y.atan2(tube.dot(&ring))/(2.0*PI);
+  return Some((t, torus.rotation.apply(normal), Vector2::new(around(p), v)));
+}
+
+//The tangents of the primitives follow the u direction of their UVs, with the side v grows on in a (see detail_normal).
+//Each takes the hit point and normal from its intersect function, and gives None where the UVs pinch to a point.
+pub fn sphere_tangent(normal: Vector3) -> Option<Vector4>{
+  return uv_tangent(normal, Vector3::new(-normal.z, 0.0, normal.x), Vector3::new(0.0, 1.0, 0.0));
+}
+
+pub fn plane_tangent(plane: &Plane) -> Option<Vector4>{
+  let (tangent, bitangent) = plane_basis(plane.normal);
+  return uv_tangent(plane.normal, tangent, bitangent);
+}
+
+pub fn box_tangent(cuboid: &Cuboid, normal: Vector3) -> Option<Vector4>{
+  let local = cuboid.rotation.transpose().apply(normal);
+  let i = if local.x.abs() > 0.5 {0} else if local.y.abs() > 0.5 {1} else {2};
+  return uv_tangent(normal, cuboid.rotation.apply(unit(i + 1)), cuboid.rotation.apply(unit(i + 2)));
+}
+
+pub fn cylinder_tangent(cylinder: &Cylinder, normal: Vector3) -> Option<Vector4>{
+  let local = cylinder.rotation.transpose().apply(normal);
+  return axial_tangent(cylinder.rotation, normal, local, local.y.abs() > 0.5);
+}
+
+pub fn cone_tangent(cone: &Cone, normal: Vector3) -> Option<Vector4>{
+  //The side of a flat cone can face mostly up, only the base faces down.
+  let local = cone.rotation.transpose().apply(normal);
+  return axial_tangent(cone.rotation, normal, local, local.y < -0.5);
+}
+
+pub fn disk_tangent(disk: &Disk, normal: Vector3) -> Option<Vector4>{
+  return uv_tangent(normal, disk.rotation.apply(unit(0)), disk.rotation.apply(unit(2)));
+}
+
+pub fn torus_tangent(torus: &Torus, point: Vector3, normal: Vector3) -> Option<Vector4>{
+  let p = torus.rotation.transpose().apply(point - torus.transform);
+  let ring = Vector3::new(p.x, 0.0, p.z).normalize();
+  let tube = p - ring*torus.major_radius;
+  let along_v = unit(1)*tube.dot(&ring) - ring*tube.y;
+  return uv_tangent(normal, torus.rotation.apply(Vector3::new(-p.z, 0.0, p.x)), torus.rotation.apply(along_v));
+}
+
+//Cylinders and cones wrap u around their axis on the side, the caps are mapped like disks.
+fn axial_tangent(rotation: Matrix3, normal: Vector3, local: Vector3, cap: bool) -> Option<Vector4>{
+  if cap{
+    return uv_tangent(normal, rotation.apply(unit(0)), rotation.apply(unit(2)));
+  }
+  return uv_tangent(normal, rotation.apply(Vector3::new(-local.z, 0.0, local.x)), rotation.apply(unit(1)));
+}
+
+//Flattens the direction u grows in onto the surface, and notes which side of it v grows on.
+fn uv_tangent(normal: Vector3, along_u: Vector3, along_v: Vector3) -> Option<Vector4>{
+  let tangent = (along_u - normal*normal.dot(&along_u)).normalize();
+  if tangent.magnitude() == 0.0{
+    return None;
+  }
+  let side = if normal.cross(&tangent).dot(&along_v) < 0.0 {-1.0} else {1.0};
+  return Some(Vector4::new(tangent.x, tangent.y, tangent.z, side));
+}
+
+//One of the axes (wrapping around after z).
+fn unit(i: usize) -> Vector3{
+  match i % 3{
+    0 => Vector3::new(1.0, 0.0, 0.0),
+    1 => Vector3::new(0.0, 1.0, 0.0),
+    _ => Vector3::new(0.0, 0.0, 1.0),
+  }
+}
+
+//Moves a ray into the space of a rotated primitive sitting at the origin.
+fn local_ray(ray: Ray, transform: Vector3, rotation: Matrix3) -> (Vector3, Vector3){
+  let inverse = rotation.transpose();
+  return (inverse.apply(ray.origin - transform), inverse.apply(ray.direction));
+}
+
+//A flat circle at a height along y, the normal points up or down (side).
+fn cap_intersect(origin: Vector3, direction: Vector3, height: f32, radius: f32, side: f32) -> Option<(f32, Vector3, Vector2)>{
+  if direction.y.abs() < 1e-9{
+    return None;
+  }
+  let t = (height - origin.y)/direction.y;
+  let p = origin + direction*t;
+  if t <= EPSILON || p.x*p.x + p.z*p.z > radius*radius{
+    return None;
+  }
+  return Some((t, Vector3::new(0.0, side, 0.0), Vector2::new(0.5 + p.x/(2.0*radius), 0.5 + p.z/(2.0*radius))));
+}
+
+//How far around the y axis a point is, from 0 to 1.
+fn around(p: Vector3) -> f32{
+  0.5 + p.z.atan2(p.x)/(2.0*PI)
+}
+
+fn nearest(closest: Option<(f32, Vector3, Vector2)>, hit: (f32, Vector3, Vector2)) -> Option<(f32, Vector3, Vector2)>{
+  match closest{
+    Some(c) if c.0 <= hit.0 => Some(c),
+    _ => Some(hit),
+  }
+}
+
+fn nearest_option(closest: Option<(f32, Vector3, Vector2)>, hit: Option<(f32, Vector3, Vector2)>) -> Option<(f32, Vector3, Vector2)>{
+  match hit{
+    Some(hit) => nearest(closest, hit),
+    None => closest,
+  }
+}
+
+//Both roots of at^2 + bt + c, nearest first (a line when a is zero).
+fn quadratic_roots(a: f32, b: f32, c: f32) -> Vec<f32>{
+  if a.abs() < 1e-9{
+    if b.abs() < 1e-9{
+      return Vec::new();
+    }
+    return vec![-c/b];
+  }
+  let discriminant = b*b - 4.0*a*c;
+  if discriminant < 0.0{
+    return Vec::new();
+  }
+  let root = discriminant.sqrt();
+  let t0 = (-b - root)/(2.0*a);
+  let t1 = (-b + root)/(2.0*a);
+  return if t0 < t1 {vec![t0, t1]} else {vec![t1, t0]};
+}
+
+//Polynomial coefficients are stored lowest power first.
+const EQUATION_EPSILON: f64 = 1e-9;
+
+fn is_zero(x: f64) -> bool{
+  x.abs() < EQUATION_EPSILON
+}
+
+fn solve_quadric(c: [f64; 3]) -> Vec<f64>{
+  let p = c[1]/(2.0*c[2]);
+  let q = c[0]/c[2];
+  let d = p*p - q;
+  if is_zero(d){
+    return vec![-p];
+  }
+  if d < 0.0{
+    return Vec::new();
+  }
+  let root = d.sqrt();
+  return vec![root - p, -root - p];
+}
+
+//Cardano's formula.
+fn solve_cubic(c: [f64; 4]) -> Vec<f64>{
+  let a = c[2]/c[3];
+  let b = c[1]/c[3];
+  let cc = c[0]/c[3];
+  //Substitute x = y - a/3 to get rid of the square term.
+  let sq_a = a*a;
+  let p = (-sq_a/3.0 + b)/3.0;
+  let q = (2.0/27.0*a*sq_a - a*b/3.0 + cc)/2.0;
+  let cb_p = p*p*p;
+  let d = q*q + cb_p;
+  let mut roots: Vec<f64> = if is_zero(d){
+    if is_zero(q){
+      vec![0.0]
+    }
+    else{
+      let u = (-q).cbrt();
+      vec![2.0*u, -u]
+    }
+  }
+  else if d < 0.0{
+    //Three real roots.
+    let phi = (-q/(-cb_p).sqrt()).max(-1.0).min(1.0).acos()/3.0;
+    let t = 2.0*(-p).sqrt();
+    vec![t*phi.cos(), -t*(phi + std::f64::consts::PI/3.0).cos(), -t*(phi - std::f64::consts::PI/3.0).cos()]
+  }
+  else{
+    let root = d.sqrt();
+    vec![(root - q).cbrt() - (root + q).cbrt()]
+  };
+  for r in roots.iter_mut(){
+    *r -= a/3.0;
+  }
+  return roots;
+}
+
+//Ferrari's method, through the resolvent cubic.
+fn solve_quartic(c: [f64; 5]) -> Vec<f64>{
+  let a = c[3]/c[4];
+  let b = c[2]/c[4];
+  let cc = c[1]/c[4];
+  let d = c[0]/c[4];
+  //Substitute x = y - a/4 to get rid of the cubic term.
+  let sq_a = a*a;
+  let p = -3.0/8.0*sq_a + b;
+  let q = sq_a*a/8.0 - a*b/2.0 + cc;
+  let r = -3.0/256.0*sq_a*sq_a + sq_a*b/16.0 - a*cc/4.0 + d;
+  let mut roots: Vec<f64> = Vec::new();
+  if is_zero(r){
+    //y(y^3 + py + q) = 0
+    roots.push(0.0);
+    roots.extend(solve_cubic([q, p, 0.0, 1.0]));
+  }
+  else{
+    let z = solve_cubic([r*p/2.0 - q*q/8.0, -r, -p/2.0, 1.0])[0];
+    let mut u = z*z - r;
+    let mut v = 2.0*z - p;
+    if is_zero(u){
+      u = 0.0;
+    }
+    else if u > 0.0{
+      u = u.sqrt();
+    }
+    else{
+      return roots;
+    }
+    if is_zero(v){
+      v = 0.0;
+    }
+    else if v > 0.0{
+      v = v.sqrt();
+    }
+    else{
+      return roots;
+    }
+    roots.extend(solve_quadric([z - u, if q < 0.0 {-v} else {v}, 1.0]));
+    roots.extend(solve_quadric([z + u, if q < 0.0 {v} else {-v}, 1.0]));
+  }
+  for root in roots.iter_mut(){
+    *root -= a/4.0;
+  }
+  return roots;
+}
+
+//A couple of Newton steps on a root of the polynomial.
+fn newton(c: &[f64; 5], mut x: f64) -> f64{
+  for _ in 0..2{
+    let value = (((c[4]*x + c[3])*x + c[2])*x + c[1])*x + c[0];
+    let slope = ((4.0*c[4]*x + 3.0*c[3])*x + 2.0*c[2])*x + c[1];
+    if slope.abs() < EQUATION_EPSILON{
+      break;
+    }
+    x -= value/slope;
+  }
+  return x;
+}
+
+fn axis(v: Vector3, i: usize) -> f32{
+  match i{
+    0 => v.x,
+    1 => v.y,
+    _ => v.z,
+  }
+}
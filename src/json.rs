@@ -0,0 +1,253 @@
+//A JSON value, objects keep their keys in the order of the file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json{
+  Null,
+  Bool(bool),
+  Number(f64),
+  String(String),
+  Array(Vec<Json>),
+  Object(Vec<(String, Json)>),
+}
+
+//What missing keys and wrong types give back, so lookups can be chained without checking every step.
+static NULL: Json = Json::Null;
+
+impl Json{
+
+  pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser {chars: text.chars().collect(), position: 0};
+    let value = parser.value()?;
+    parser.whitespace();
+    if parser.position < parser.chars.len() {
+      return Err(parser.error("unexpected text after the JSON value"));
+    }
+    return Ok(value);
+  }
+
+  //The value of a key in an object, or null.
+  pub fn get(&self, key: &str) -> &Json {
+    match self {
+      Json::Object(members) => members.iter().find(|(k, _)| k == key).map_or(&NULL, |(_, v)| v),
+      _ => &NULL,
+    }
+  }
+
+  //An item of an array, or null.
+  pub fn at(&self, index: usize) -> &Json {
+    match self {
+      Json::Array(items) => items.get(index).unwrap_or(&NULL),
+      _ => &NULL,
+    }
+  }
+
+  pub fn is_null(&self) -> bool {
+    *self == Json::Null
+  }
+
+  pub fn as_bool(&self) -> Option<bool> {
+    match self {
+      Json::Bool(b) => Some(*b),
+      _ => None,
+    }
+  }
+
+  pub fn as_f64(&self) -> Option<f64> {
+    match self {
+      Json::Number(n) => Some(*n),
+      _ => None,
+    }
+  }
+
+  pub fn as_f32_or(&self, default: f32) -> f32 {
+    self.as_f64().map_or(default, |n| n as f32)
+  }
+
+  pub fn as_usize(&self) -> Option<usize> {
+    match self {
+      Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+      _ => None,
+    }
+  }
+
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Json::String(s) => Some(s),
+      _ => None,
+    }
+  }
+
+  //The items of an array, an empty slice for anything else.
+  pub fn items(&self) -> &[Json] {
+    match self {
+      Json::Array(items) => items,
+      _ => &[],
+    }
+  }
+
+  //An array of numbers, like the vectors and matrices of glTF.
+  pub fn as_floats(&self) -> Option<Vec<f32>> {
+    match self {
+      Json::Array(items) => items.iter().map(|i| i.as_f64().map(|n| n as f32)).collect(),
+      _ => None,
+    }
+  }
+
+}
+
+struct Parser{
+  chars: Vec<char>,
+  position: usize,
+}
+
+impl Parser{
+
+  fn error(&self, message: &str) -> String {
+    format!("{} at character {}", message, self.position)
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.position).cloned()
+  }
+
+  fn whitespace(&mut self) {
+    while let Some(c) = self.peek() {
+      if !c.is_whitespace() {
+        break;
+      }
+      self.position += 1;
+    }
+  }
+
+  fn expect(&mut self, word: &str) -> Result<(), String> {
+    for c in word.chars() {
+      if self.peek() != Some(c) {
+        return Err(self.error(&format!("expected '{}'", word)));
+      }
+      self.position += 1;
+    }
+    Ok(())
+  }
+
+  fn value(&mut self) -> Result<Json, String> {
+    self.whitespace();
+    match self.peek() {
+      Some('{') => self.object(),
+      Some('[') => self.array(),
+      Some('"') => Ok(Json::String(self.string()?)),
+      Some('t') => self.expect("true").map(|_| Json::Bool(true)),
+      Some('f') => self.expect("false").map(|_| Json::Bool(false)),
+      Some('n') => self.expect("null").map(|_| Json::Null),
+      Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+      Some(_) => Err(self.error("unexpected character")),
+      None => Err(self.error("the JSON ends too early")),
+    }
+  }
+
+  fn object(&mut self) -> Result<Json, String> {
+    self.position += 1;
+    let mut members: Vec<(String, Json)> = Vec::new();
+    self.whitespace();
+    if self.peek() == Some('}') {
+      self.position += 1;
+      return Ok(Json::Object(members));
+    }
+    loop {
+      self.whitespace();
+      if self.peek() != Some('"') {
+        return Err(self.error("expected a key"));
+      }
+      let key = self.string()?;
+      self.whitespace();
+      self.expect(":")?;
+      members.push((key, self.value()?));
+      self.whitespace();
+      match self.peek() {
+        Some(',') => self.position += 1,
+        Some('}') => {
+          self.position += 1;
+          return Ok(Json::Object(members));
+        },
+        _ => return Err(self.error("expected ',' or '}'")),
+      }
+    }
+  }
+
+  fn array(&mut self) -> Result<Json, String> {
+    self.position += 1;
+    let mut items: Vec<Json> = Vec::new();
+    self.whitespace();
+    if self.peek() == Some(']') {
+      self.position += 1;
+      return Ok(Json::Array(items));
+    }
+    loop {
+      items.push(self.value()?);
+      self.whitespace();
+      match self.peek() {
+        Some(',') => self.position += 1,
+        Some(']') => {
+          self.position += 1;
+          return Ok(Json::Array(items));
+        },
+        _ => return Err(self.error("expected ',' or ']'")),
+      }
+    }
+  }
+
+  fn string(&mut self) -> Result<String, String> {
+    self.position += 1;
+    let mut text = String::new();
+    loop {
+      let c = self.peek().ok_or_else(|| self.error("a string isn't closed"))?;
+      self.position += 1;
+      match c {
+        '"' => return Ok(text),
+        '\\' => {
+          let escaped = self.peek().ok_or_else(|| self.error("a string isn't closed"))?;
+          self.position += 1;
+          match escaped {
+            '"' => text.push('"'),
+            '\\' => text.push('\\'),
+            '/' => text.push('/'),
+            'b' => text.push('\u{8}'),
+            'f' => text.push('\u{c}'),
+            'n' => text.push('\n'),
+            'r' => text.push('\r'),
+            't' => text.push('\t'),
+            'u' => {
+              let mut code = self.hex()?;
+              //Characters outside the basic plane come as two escapes (a surrogate pair).
+              if (0xD800..0xDC00).contains(&code) && self.chars.get(self.position) == Some(&'\\') && self.chars.get(self.position + 1) == Some(&'u') {
+                self.position += 2;
+                let low = self.hex()?;
+                code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+              }
+              text.push(std::char::from_u32(code).unwrap_or('\u{FFFD}'));
+            },
+            _ => return Err(self.error("unknown escape in a string")),
+          }
+        },
+        _ => text.push(c),
+      }
+    }
+  }
+
+  fn hex(&mut self) -> Result<u32, String> {
+    let digits: String = self.chars.iter().skip(self.position).take(4).collect();
+    self.position += 4;
+    u32::from_str_radix(&digits, 16).map_err(|_| self.error("bad unicode escape"))
+  }
+
+  fn number(&mut self) -> Result<Json, String> {
+    let start = self.position;
+    while let Some(c) = self.peek() {
+      if !(c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E') {
+        break;
+      }
+      self.position += 1;
+    }
+    let text: String = self.chars[start..self.position].iter().collect();
+    text.parse().map(Json::Number).map_err(|_| self.error(&format!("bad number '{}'", text)))
+  }
+
+}
@@ -3,32 +3,106 @@
 mod definitions;
 mod interpreter;
 mod model;
-
-//Import our model struct from model.rs
-use model::Model;
+mod random;
+mod animation;
+mod output;
+mod tonemap;
+mod exr;
+mod passes;
+mod denoise;
+mod film;
+mod interrupt;
+mod checkpoint;
+mod intersect;
+mod csg;
+mod sdf;
+mod bvh;
+mod ply;
+mod stl;
+mod json;
+mod gltf;
+mod instance;
+mod meshcache;
+mod texture;
+mod subdivision;
+mod procedural;
+mod media;
 
 //Import interpreter.rs
 use interpreter::interpreter;
 
 //Import all of our structs from definitions.rs
-use definitions::Vector4;
 use definitions::Vector3;
 use definitions::Vector2;
-use definitions::Light;
-use definitions::Sphere;
+use definitions::Vector4;
+use definitions::Matrix3;
 use definitions::Material;
+use definitions::Ray;
+use definitions::Light;
+use definitions::Hit;
+use definitions::Scene;
+use definitions::Settings;
+
+//Import the image writers from output.rs
+use output::write_framebuffer;
+use output::frame_filename;
+
+//Import our random number generator from random.rs
+use random::Rng;
+use random::hash;
+use random::radical_inverse;
+
+//Import the ray-object intersections from intersect.rs
+use intersect::{sphere_intersect, mesh_intersect, plane_intersect, box_intersect};
+use intersect::{cylinder_intersect, cone_intersect, disk_intersect, torus_intersect};
+use intersect::{sphere_tangent, plane_tangent, box_tangent, cylinder_tangent, cone_tangent, disk_tangent, torus_tangent};
+
+//Import the constructive solid geometry from csg.rs
+use csg::csg_intersect;
+
+//Import the signed distance fields from sdf.rs
+use sdf::sdf_intersect;
+
+//Import the normal and bump maps from texture.rs
+use texture::detail_normal;
+
+//Import the procedural textures from procedural.rs
+use procedural::{Frame, apply_patterns};
+
+//Import the mesh loading and its cache from model.rs, instance.rs and meshcache.rs
+use model::load_mesh;
+use instance::MeshAsset;
+use meshcache::{write_cache, cache_path};
+
+//Import the sample accumulation from film.rs
+use film::Film;
+use film::PixelSamples;
+
+//Import the render passes from passes.rs
+use passes::Aov;
+
+//Import the denoiser from denoise.rs
+use denoise::denoise;
+
+//Import the checkpoints from checkpoint.rs
+use checkpoint::{scene_hash, write_checkpoint, read_checkpoint};
+
+//Import the Ctrl+C handling from interrupt.rs
+use interrupt::interrupted;
 
 //Import all of the standard libraries we need.
-use std::fs::File;
 use std::io::{self, Write};
 use std::mem;
+use std::env;
+use std::fs;
+use std::process;
 use std::thread;
-use std::time::{Instant};
-use std::sync::{Arc};
+use std::time::{Instant, Duration};
 use std::sync::mpsc::sync_channel;
 
 //Constant Variables
 const FOURX_AA: [Vector2; 4] = [Vector2{x: 0.25, y: 0.25}, Vector2{x: -0.25, y: 0.25}, Vector2{x: 0.25, y: -0.25}, Vector2{x: -0.25, y:-0.25}];
+const NO_AA: [Vector2; 1] = [Vector2{x: 0.0, y: 0.0}];
 
 
 //Divide two usizes and return a float.
@@ -63,196 +137,304 @@ fn reflect(i: Vector3, normal: Vector3) -> Vector3{
   return i - normal*2.0*(i.dot(&normal));
 }
 
-//Checks if a ray hits a sphere.
-fn sphere_intersect(sphere: Sphere, origin: Vector3, direction: Vector3) -> Option<f32>{
-  let length = sphere.transform - origin;
-  let ray = length.dot(&direction);
-  let difference_of_squares = (length.dot(&length)) - ray*ray;
-  if difference_of_squares > sphere.radius*sphere.radius{
-    return None;
+//Runs through list of objects in the scene and checks for intersection.
+fn scene_intersect(ray: Ray, scene: &Scene) -> Option<Hit>{
+  //The frame of the closest object is kept with it, so patterns in object space can be looked up once we know what was hit.
+  let mut closest: Option<(Hit, Frame)> = None;
+  let mut closest_object = f32::MAX;
+  //Meshes bend their normals with their own tangents and CSG objects with the maps of their leaves, the maps of everything else are applied here to the closest hit.
+  //The tangent is only worked out for the closest hit so far, from its point and normal.
+  let mut consider = |found: Option<(f32, Vector3, Vector2)>, material: &Material, id: u32, detailed: bool, tangent: &dyn Fn(Vector3, Vector3) -> Option<Vector4>, frame: Frame| {
+    if let Some((t, normal, uv)) = found{
+      if t < closest_object{
+        closest_object = t;
+        let point = ray.origin + ray.direction*t;
+        let tangent = tangent(point, normal);
+        let normal = if detailed {normal} else {detail_normal(material, normal, tangent, uv)};
+        closest = Some((Hit::new(point, normal, material.clone(), t, id, uv, tangent), frame));
+      }
+    }
+  };
+  let still = Matrix3::identity();
+  for sphere in &scene.spheres {
+    consider(sphere_intersect(sphere, ray), &sphere.material, sphere.id, false, &|_, n| sphere_tangent(n), Frame::new(sphere.transform_at(ray.time), still, 1.0));
   }
-  let temp = (sphere.radius*sphere.radius - difference_of_squares).sqrt();
-  let mut distance = ray - temp;
-  let point1 = ray + temp;
-  if distance < 0.0{
-    distance = point1;
+  for plane in &scene.planes {
+    consider(plane_intersect(plane, ray), &plane.material, plane.id, false, &|_, _| plane_tangent(plane), Frame::new(plane.point, still, 1.0));
   }
-  if !(distance < 0.0){
-    return Some(distance);
+  for cuboid in &scene.boxes {
+    consider(box_intersect(cuboid, ray), &cuboid.material, cuboid.id, false, &|_, n| box_tangent(cuboid, n), Frame::new(cuboid.center(), cuboid.rotation, 1.0));
   }
-  else{
-    return None;
+  for cylinder in &scene.cylinders {
+    consider(cylinder_intersect(cylinder, ray), &cylinder.material, cylinder.id, false, &|_, n| cylinder_tangent(cylinder, n), Frame::new(cylinder.transform, cylinder.rotation, 1.0));
+  }
+  for cone in &scene.cones {
+    consider(cone_intersect(cone, ray), &cone.material, cone.id, false, &|_, n| cone_tangent(cone, n), Frame::new(cone.transform, cone.rotation, 1.0));
+  }
+  for disk in &scene.disks {
+    consider(disk_intersect(disk, ray), &disk.material, disk.id, false, &|_, n| disk_tangent(disk, n), Frame::new(disk.transform, disk.rotation, 1.0));
+  }
+  for torus in &scene.tori {
+    consider(torus_intersect(torus, ray), &torus.material, torus.id, false, &|p, n| torus_tangent(torus, p, n), Frame::new(torus.transform, torus.rotation, 1.0));
   }
+  for mesh in &scene.meshes {
+    if let Some((t, normal, uv, tangent, material)) = mesh_intersect(mesh, ray){
+      consider(Some((t, normal, uv)), &material, mesh.id, true, &|_, _| tangent, Frame::new(mesh.transform_at(ray.time), still, 1.0));
+    }
+  }
+  for sdf in &scene.sdfs {
+    consider(sdf_intersect(sdf, ray), &sdf.material, sdf.id, false, &|_, _| None, Frame::world());
+  }
+  for csg in &scene.csg {
+    if let Some((t, normal, uv, tangent, material)) = csg_intersect(csg, ray){
+      consider(Some((t, normal, uv)), &material, csg.id, true, &|_, _| tangent, Frame::world());
+    }
+  }
+  //Instances are found through their BVH last, so it can skip everything farther than what we've already hit.
+  let mut instance_hit: Option<((f32, Vector3, Vector2, Option<Vector4>, Material), usize)> = None;
+  scene.instance_bvh.traverse(ray, closest_object, |i| {
+    let hit = scene.instances[i].intersect(ray)?;
+    let t = hit.0;
+    if t < closest_object && instance_hit.as_ref().map_or(true, |h| t < (h.0).0) {
+      instance_hit = Some((hit, i));
+    }
+    Some(t)
+  });
+  if let Some(((t, normal, uv, tangent, material), i)) = instance_hit {
+    let instance = &scene.instances[i];
+    closest = Some((Hit::new(ray.origin + ray.direction*t, normal, material, t, instance.id, uv, tangent), Frame::new(instance.transform, instance.rotation, instance.scale)));
+  }
+  return closest.map(|(mut hit, frame)| {
+    apply_patterns(&mut hit, &frame);
+    hit
+  });
 }
 
-//Checks if a ray hits a triangle (normally in a mesh).
-fn triangle_intersect(origin: Vector3, direction: Vector3, v0: Vector3, v1: Vector3, v2: Vector3, transform: Vector3) -> Option<(f32, Vector3)> {
-  let v0 = v0 + transform;
-  let v1 = v1 + transform;
-  let v2 = v2 + transform;
-  let edge1 = v1 - v0;
-  let edge2 = v2 - v0;
-  let h = direction.cross(&edge2);
-  let a = edge1.dot(&h);
+//Shades a hit, returning the direct light, the light from reflections and refractions, and how much of the lighting was shadowed.
+fn shade(ray: Ray, hit: &Hit, scene: &Scene, depth: i32, path_depth: i32) -> (Vector3, Vector3, f32){
+  let direction = ray.direction;
+  let lights = &scene.lights;
+  let point = hit.point;
+  let normal = hit.normal;
+  let material = &hit.material;
+  let mut diffuse_light_intensity: f32 = 0.0;
+  let mut specular_light_intensity: f32 = 0.0;
+  let mut shadowed: f32 = 0.0;
+  let mut reflect_color: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+  let mut refract_color: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+
+  //Checking for reflection and refraction, these don't depend on the light so we only trace them once.
+  if !lights.is_empty(){
+    let reflect_direction: Vector3 = reflect(direction, normal).normalize();
+    let refract_direction: Vector3 = refract(direction, normal, material.refractive_index).normalize();
+    let mut reflect_origin: Vector3 = point + (normal * 0.001);
+    let mut refract_origin: Vector3 = point + (normal * 0.001);
+    if reflect_direction.dot(&normal) < 0.0{
+      reflect_origin = point - (normal * 0.001);
+    }
 
-  if a.abs() < 0.0001{ 
-    return None; 
+    if refract_direction.dot(&normal) < 0.0{
+      refract_origin = point - (normal * 0.001);
+    }
+
+    reflect_color = cast_ray(Ray::new(reflect_origin, reflect_direction, ray.time), scene, depth + 1, path_depth);
+    refract_color = cast_ray(Ray::new(refract_origin, refract_direction, ray.time), scene, depth + 1, path_depth);
   }
 
-  let f = 1.0 / a;
-  let s = origin - v0;
-  let u = f * s.dot(&h);
+  for i in 0..lights.len(){
+    let light_direction: Vector3 = (lights[i].transform - point).normalize();
+    let light_distance = (lights[i].transform - point).magnitude();
+    //Checking for shadows here.
+    let mut shadow_origin = point + (normal * 0.001);
 
-  if !(0.0..=1.0).contains(&u){ 
-    return None; 
-  }
+    if light_direction.dot(&normal) < 0.0{
+      shadow_origin = point - (normal * 0.001);
+    }
 
-  let q = s.cross(&edge1);
-  let v = f * direction.dot(&q);
+    let shadow_ray = Ray::new(shadow_origin, light_direction, ray.time);
+    if let Some(shadow_hit) = scene_intersect(shadow_ray, scene){
+      if shadow_hit.distance < light_distance{
+        shadowed += 1.0;
+        continue;
+      }
+    }
+    //Fog and volumes between the surface and the light dim it.
+    let intensity = lights[i].intensity * scene.media.transmittance(shadow_ray, light_distance);
+    diffuse_light_intensity += intensity * light_direction.dot(&normal).max(0.0);
+    specular_light_intensity += (f32::max(0.0, (reflect(light_direction * -1.0, normal) * -1.0).dot(&direction))).powf(material.specular_exponent) * intensity;
+  }
+  let direct = (material.diffuse_color * diffuse_light_intensity * material.albedo.x) + ((Vector3::new(1.0, 1.0, 1.0)) * specular_light_intensity * material.albedo.y);
+  let indirect = reflect_color*material.albedo.z + refract_color*material.albedo.a;
+  let shadow = if lights.is_empty() {0.0} else {shadowed/(lights.len() as f32)};
+  return (direct, indirect, shadow);
+}
 
-  if v < 0.0 || u + v > 1.0{ 
-    return None; 
+//Raycast function, uses reflection, refraction, and calculates shadows.
+fn cast_ray(ray: Ray, scene: &Scene, depth: i32, path_depth: i32) -> Vector3{
+  //Check if we've exceeded the path depth to limit render times.
+  if depth <= path_depth{
+    if let Some(hit) = scene_intersect(ray, scene) {
+      //Compute the final color of the pixel.
+      let (direct, indirect, _) = shade(ray, &hit, scene, depth, path_depth);
+      return through_media(ray, hit.distance, direct + indirect, scene);
+    }
   }
+  //If nothing is hit, just return the background color of the render.
+  return through_media(ray, f32::INFINITY, scene.background_color, scene);
+}
 
-  let t = f * edge2.dot(&q);
-  if t > 0.0001{
-    let normal = edge1.cross(&edge2).normalize();
-    return Some((t, normal));
-  } 
-  else{
-    return None;
+//What a ray sees after the fog and volumes on its way (up to distance, infinite when it hit nothing) have dimmed it and scattered light into it.
+fn through_media(ray: Ray, distance: f32, color: Vector3, scene: &Scene) -> Vector3{
+  if scene.media.is_empty() {
+    return color;
   }
+  let visible = |point: Vector3, light: &Light| {
+    let to_light = light.transform - point;
+    let light_distance = to_light.magnitude();
+    match scene_intersect(Ray::new(point, to_light*(1.0/light_distance), ray.time), scene) {
+      Some(hit) => hit.distance >= light_distance,
+      None => true,
+    }
+  };
+  return scene.media.apply(ray, distance, color, &scene.lights, &visible);
 }
 
-//Runs through list of objects in the scene and checks for intersection.
-fn scene_intersect<'a>(origin: Vector3, direction: Vector3, spheres: &Vec<Sphere>, meshes: &Vec<Model>, mut hit: Vector3, mut n: Vector3, mut material: Material) -> Option<(Vector3, Vector3, Material)>{
-  let mut closest_object = f32::MAX;
-  for i in 0..spheres.len() {
-    if let Some(dist_i) = sphere_intersect(spheres[i], origin, direction){
-      if dist_i < closest_object {
-        closest_object = dist_i;
-        hit = origin + direction*dist_i;
-        n = (hit-spheres[i].transform).normalize();
-        material = spheres[i].material;
-      }
+//Traces a camera ray and also records what it hit for the render passes.
+fn cast_camera_ray(ray: Ray, scene: &Scene, path_depth: i32) -> (Vector3, Aov){
+  let mut aov = Aov::background(scene.background_color);
+  if 0 <= path_depth{
+    if let Some(hit) = scene_intersect(ray, scene) {
+      let (direct, indirect, shadow) = shade(ray, &hit, scene, 0, path_depth);
+      let forward = (scene.camera.target - scene.camera.transform).normalize();
+      aov = Aov{
+        depth: (hit.point - ray.origin).dot(&forward),
+        normal: hit.normal,
+        uv: hit.uv,
+        albedo: hit.material.diffuse_color * hit.material.albedo.x,
+        direct: direct,
+        indirect: indirect,
+        shadow: shadow,
+        object_id: hit.object_id,
+        material_id: hit.material.id,
+        samples: 1,
+      };
+      return (through_media(ray, hit.distance, direct + indirect, scene), aov);
     }
   }
-  for mesh in meshes {
-    for face in &mesh.faces {
-      let v0 = mesh.verts[face.x as usize];
-      let v1 = mesh.verts[face.y as usize];
-      let v2 = mesh.verts[face.z as usize];
-      if let Some((t, normal)) = triangle_intersect(origin, direction, v0, v1, v2, mesh.transform) {
-        if t < closest_object {
-          closest_object = t;
-          hit = origin + direction * t;
-          n = normal;
-          material = mesh.material;
-        }
+  return (through_media(ray, f32::INFINITY, scene.background_color, scene), aov);
+}
+
+//Takes samples first..first+count of a pixel, every sample casts one ray per anti-aliasing offset.
+fn sample_pixel(scene: &Scene, settings: &Settings, x: usize, y: usize, first: u32, count: u32) -> PixelSamples{
+  let fheight = settings.fheight;
+  let fwidth = settings.fwidth;
+  let camera = scene.camera;
+  let fov: f32 = camera.fov;
+  //Cast four rays for anti-aliasing.
+  let offsets: &[Vector2] = if settings.anti_alias == 1 {&FOURX_AA} else {&NO_AA};
+  let cell = if settings.anti_alias == 1 {0.5} else {1.0};
+  let pixel = (x+y*fwidth) as u32;
+  //Shutter times follow a low discrepancy sequence, shifted per pixel so neighbours don't line up.
+  let seed = hash(settings.seed);
+  let shift = Rng::new(pixel ^ seed).next_f32();
+  let mut result = PixelSamples::new();
+  for s in first..first + count{
+    let mut rng = Rng::new(pixel ^ hash(s.wrapping_add(0x68bc21eb)) ^ seed);
+    let mut color = Vector3::new(0.0, 0.0, 0.0);
+    for i in 0..offsets.len(){
+      let time = camera.time_at((radical_inverse(s*(offsets.len() as u32) + i as u32) + shift).fract());
+      //The first sample uses the fixed pattern, later ones are jittered inside their cell so they also smooth out edges.
+      let mut offset = offsets[i];
+      if s > 0{
+        offset = Vector2{x: offset.x + (rng.next_f32() - 0.5)*cell, y: offset.y + (rng.next_f32() - 0.5)*cell};
       }
+      let transform_x = (2.0*(x as f32 + 0.5 + offset.x)/(fwidth as f32) - 1.0)*(fov/2.0).tan()*udiv(fwidth, fheight);
+      let transform_y = -1.0*(2.0*(y as f32 + 0.5 + offset.y)/(fheight as f32) - 1.0)*(fov/2.0).tan();
+      let direction = camera.direction(Vector3::new(transform_x, transform_y, -1.0));
+      let (sample, sample_aov) = cast_camera_ray(Ray::new(camera.transform, direction, time), scene, settings.path_depth);
+      color = color + sample;
+      result.aov.add(&sample_aov);
     }
+    result.add(color * (1.0/(offsets.len() as f32)));
   }
-  if closest_object < 1000.0{
-    return Some((hit, n, material));
-  }
-  return None;
+  return result;
 }
 
-//Write the framebuffer to a ppm file.
-fn framebuffer_to_ppm(framebuffer: &mut Vec<Vector3>, fheight: usize, fwidth: usize) -> io::Result<()>{
-    //Open the PPM file.
-    let mut file = File::create("./out.ppm")?;
-    let start_time = Instant::now();
-    //Write the header for the PPM file (P6 format, width, height, max color value).
-    writeln!(file, "P6")?;
-    writeln!(file, "{} {}", fwidth, fheight)?;
-    writeln!(file, "255")?;
-
-    //Write the pixel data.
-    for y in 0..fheight {
-        for x in 0..fwidth {
-            //Scale between 0 to 255 and convert to u8.
-            let mut c = framebuffer[x+y*fwidth];
-            let max = f32::max(c.x, f32::max(c.y, c.z)); 
-        
-            if max > 1.0 {
-                let scale_factor = 1.0 / max;
-                c = c * scale_factor;
-            } 
-            framebuffer[x+y*fwidth] = c;
-
-            let pixel_value = Vector3::new(framebuffer[x+y*fwidth].x, framebuffer[x+y*fwidth].y, framebuffer[x+y*fwidth].z);
-            let clamped = Vector3::tou8(pixel_value * 255.0);
-            for i in clamped{
-              file.write_all(&[i])?;
-            }
-            print!("\r{:?}% of the image written to disk.", (udiv(x+y*fwidth, fheight*fwidth)*100.0 + 1.0) as i32);
-        }
-    }
-    let duration = start_time.elapsed();
-    println!("\nWriting completed in {} seconds.", duration.as_secs_f64());
-    Ok(())
+//Something done with the film every few seconds while it renders (snapshots and checkpoints).
+struct Periodic<'a>{
+  interval: f32,
+  last: Instant,
+  action: &'a dyn Fn(&Film),
 }
 
-//Raycast function, uses reflection, refraction, and calculates shadows.
-fn cast_ray(origin: Vector3, direction: Vector3, spheres: &Vec<Sphere>, lights: &Vec<Light>, meshes: &Vec<Model>, depth: i32, background_color: Vector3, path_depth: i32) -> Vector3{
-  let normal: Vector3 = Vector3::new(0.0, 0.0, 0.0);
-  let point: Vector3 = Vector3::new(0.0, 0.0, 0.0);
-  let material: Material = Material::new(Vector3::new(0.0, 0.0, 0.0), Vector4::new(0.0, 0.0, 0.0, 0.0), 0.0, 0.0);
-  let mut diffuse_light_intensity: f32 = 0.0;
-  let mut specular_light_intensity: f32 = 0.0;
+impl<'a> Periodic<'a>{
 
-  //Check if we've exceeded the path depth to limit render times.
-  if depth <= path_depth{
-    if let Some((point, normal, material)) = scene_intersect(origin, direction, &spheres, &meshes, point, normal, material) {
-      let mut reflect_color: Vector3 = Vector3::new(0.0, 0.0, 0.0);
-      let mut refract_color: Vector3 = Vector3::new(0.0, 0.0, 0.0);
-      for i in 0..lights.len(){
-        let light_direction: Vector3 = (lights[i].transform - point).normalize();
-        let light_distance = (lights[i].transform - point).magnitude();
-        //Checking for reflection and refraction.
-        let reflect_direction: Vector3 = reflect(direction, normal).normalize();
-        let refract_direction: Vector3 = refract(direction, normal, material.refractive_index).normalize();
-        let mut reflect_origin: Vector3 = point + (normal * 0.001);
-        let mut refract_origin: Vector3 = point + (normal * 0.001);
-        if reflect_direction.dot(&normal) < 0.0{
-          reflect_origin = point - (normal * 0.001);
-        }
+  fn new(interval: f32, action: &'a dyn Fn(&Film)) -> Self {
+    Periodic {interval, last: Instant::now(), action}
+  }
 
-        if refract_direction.dot(&normal) < 0.0{
-          refract_origin = point - (normal * 0.001);
-        }
+  fn tick(&mut self, film: &Film) {
+    if self.interval > 0.0 && self.last.elapsed() >= Duration::from_secs_f32(self.interval) {
+      println!();
+      (self.action)(film);
+      self.last = Instant::now();
+    }
+  }
 
-        reflect_color = cast_ray(reflect_origin, reflect_direction, spheres, lights, meshes, depth + 1, background_color, path_depth);
-        refract_color = cast_ray(refract_origin, refract_direction, spheres, lights, meshes, depth + 1, background_color, path_depth);
-        //Checking for shadows here.
-        let mut shadow_origin = point + (normal * 0.001);
-  
-        if light_direction.dot(&normal) < 0.0{
-          shadow_origin = point - (normal * 0.001);
-        }
+}
 
-        let shadow_pt: Vector3 = Vector3::new(0.0, 0.0, 0.0);
-        let shadow_n: Vector3 = Vector3::new(0.0, 0.0, 0.0);
-        let temp_material: Material = Material::new(Vector3::new(0.0, 0.0, 0.0), Vector4::new(0.0, 0.0, 0.0, 0.0), 0.0, 0.0);
-        if let Some((shadow_pt, _, _)) = scene_intersect(shadow_origin, light_direction, &spheres, &meshes, shadow_pt, shadow_n, temp_material){
-          if (shadow_pt-shadow_origin).magnitude() < light_distance{
+//Renders the number of samples the schedule asks for in every pixel and adds them to the film.
+fn render_pass(scene: &Scene, settings: &Settings, schedule: &Vec<u32>, film: &mut Film, threads: usize, pass: i32, periodic: &mut Vec<Periodic>){
+  let fheight = settings.fheight;
+  let fwidth = settings.fwidth;
+  let (tx, rx) = sync_channel(threads);
+  let first: &Vec<u32> = &film.pixels.iter().map(|p| p.count).collect();
+  let total = schedule.iter().filter(|s| **s > 0).count();
+
+  //The threads borrow the scene, so the meshes are shared instead of copied for every render.
+  thread::scope(|s| {
+    //Rows are dealt out to the threads in turn, so they stay busy even when only parts of the image need samples.
+    for j in 0..threads{
+      let tx = tx.clone();
+
+      s.spawn(move || {
+      //Iterate through each pixel in the rows and render it via ray-tracing.
+      for y in (j..fheight).step_by(threads){
+        for x in 0..fwidth{
+          let index = x+y*fwidth;
+          if schedule[index] == 0 || interrupted(){
             continue;
           }
+          //Send pixel back to main thread for assembly.
+          tx.send((sample_pixel(scene, settings, x, y, first[index], schedule[index]), index)).unwrap();
         }
-        diffuse_light_intensity += lights[i].intensity * light_direction.dot(&normal).max(0.0);
-        specular_light_intensity += (f32::max(0.0, (reflect(light_direction * -1.0, normal) * -1.0).dot(&direction))).powf(material.specular_exponent) * lights[i].intensity;
       }
-      //Compute the final color of the pixel.
-      return (material.diffuse_color * diffuse_light_intensity * material.albedo.x) + ((Vector3::new(1.0, 1.0, 1.0)) * specular_light_intensity * material.albedo.y) + reflect_color*material.albedo.z + refract_color*material.albedo.a;
+      });
     }
-  }
-  //If nothing is hit, just return the background color of the render.
-  return background_color;
+    drop(tx);
+    let mut count: i32 = 0;
+
+    //Main thread waits for new pixels and adds them to the film.
+    while let Ok(msg) = rx.recv() {
+      count = count + 1;
+      let (samples, index) = msg;
+      film.add(index, &samples);
+      if pass == 1{
+        print!("\r{:?}% of the image rendered.", (((count as f32)/(total as f32))*100.0) as i32);
+      }
+      else{
+        print!("\r{:?}% of pass {} rendered ({} pixels).", (((count as f32)/(total as f32))*100.0) as i32, pass, total);
+      }
+      for p in periodic.iter_mut(){
+        p.tick(film);
+      }
+    }
+  });
+  println!();
 }
 
-//Our main rendering function that takes in our objects and lights.
-fn render(spheres: &Vec<Sphere>, lights: &Vec<Light>, meshes: &Vec<Model>, background_color: Vector3, path_depth: i32, fheight: usize, fwidth: usize, anti_alias: i32){
+//Our main rendering function that takes in our scene and adds samples to the film until it is done (it may already have some from a checkpoint).
+fn render(scene: &Scene, settings: &Settings, mut film: Film, periodic: &mut Vec<Periodic>) -> Film{
   let mut threads = 1 as usize;
   //Check how many threads we have access to.
   match thread::available_parallelism() {
@@ -267,87 +449,208 @@ fn render(spheres: &Vec<Sphere>, lights: &Vec<Light>, meshes: &Vec<Model>, backg
   
   //Initialize all of our data structres for the threads.
   let start_time = Instant::now();
-  let mut handles = vec![];
-  let (tx, rx) = sync_channel(threads);
-
-  let mut framebuffer: Vec<Vector3> = vec![Vector3::new(0.0, 0.0, 0.0); fwidth * fheight];
-  let fov: f32 = 1.0;
-
-  let spheres_arc = Arc::new(spheres.clone());
-  let lights_arc = Arc::new(lights.clone());
-  let meshes_arc = Arc::new(meshes.clone());
-
-
-  //Create a chunk of the render for each thread to compute.
-  for j in 0..threads{
-    let start_y = ((j as f32)*udiv(fheight, threads)) as usize;
-    let end_y = ((j as f32 + 1.0)*udiv(fheight, threads)) as usize;
-    let tx = tx.clone();
-    let spheres_clone = Arc::clone(&spheres_arc);
-    let lights_clone = Arc::clone(&lights_arc);
-    let meshes_clone = Arc::clone(&meshes_arc);
-
-    let handle = thread::spawn(move || {
-    //Iterate through each pixel in the chunk and render it via ray-tracing.
-    for y in start_y..end_y{
-      for x in 0..fwidth{
-        let mut color = Vector3::new(0.0, 0.0, 0.0);
-        //Cast four rays for anti-aliasing.
-        if anti_alias == 1{
-          for i in 0..FOURX_AA.len(){
-            let transform_x = (2.0*(x as f32 + 0.5 + FOURX_AA[i].x)/(fwidth as f32) - 1.0)*(fov/2.0).tan()*udiv(fwidth, fheight);
-            let transform_y = -1.0*(2.0*(y as f32 + 0.5 + FOURX_AA[i].y)/(fheight as f32) - 1.0)*(fov/2.0).tan();
-            let direction = Vector3::new(transform_x, transform_y, -1.0).normalize();
-            color = color + (cast_ray(Vector3::new(0.0, 0.0, 0.0), direction, &*spheres_clone, &*lights_clone, &*meshes_clone, 0, background_color, path_depth)) * (1.0/(FOURX_AA.len() as f32));
-          }
-        }
-        else{
-          let transform_x = (2.0*(x as f32 + 0.5)/(fwidth as f32) - 1.0)*(fov/2.0).tan()*udiv(fwidth, fheight);
-          let transform_y = -1.0*(2.0*(y as f32 + 0.5)/(fheight as f32) - 1.0)*(fov/2.0).tan();
-          let direction = Vector3::new(transform_x, transform_y, -1.0).normalize();
-          color = cast_ray(Vector3::new(0.0, 0.0, 0.0), direction, &*spheres_clone, &*lights_clone, &*meshes_clone, 0, background_color, path_depth);
+  let adaptive = settings.adaptive_samples > 0;
+  //Adaptive sampling needs at least two samples to estimate the noise.
+  let initial = if adaptive {i32::max(settings.samples, 2)} else {i32::max(settings.samples, 1)} as u32;
+  //Progressive renders work up to that 1, 2, 4... samples at a time, so there is a full image to look at early on.
+  let mut target = Some(if settings.progressive {1} else {initial});
+  let mut pass = 1;
+
+  //Sample everything evenly, then adaptive passes go back to the pixels that are still noisy.
+  //Pixels that already have enough samples are skipped, which is how a resumed render picks up where it stopped.
+  while !interrupted(){
+    let mut schedule: Vec<u32> = match target{
+      Some(t) => film.pixels.iter().map(|p| t.saturating_sub(p.count)).collect(),
+      None if adaptive => film.adaptive_schedule(settings.adaptive_threshold, settings.adaptive_samples as u32),
+      None => break,
+    };
+    //Nothing outside the render region gets sampled.
+    for (i, s) in schedule.iter_mut().enumerate(){
+      if !settings.region.contains(i % settings.fwidth, i / settings.fwidth){
+        *s = 0;
+      }
+    }
+    if schedule.iter().any(|s| *s > 0){
+      render_pass(scene, settings, &schedule, &mut film, threads, pass, periodic);
+      pass += 1;
+      if let Some(t) = target{
+        if settings.progressive && !interrupted(){
+          println!("{} samples per pixel so far.", t);
         }
-        //Send pixel back to main thread for assembly.
-        tx.send((color, x+y*fwidth)).unwrap();
       }
     }
-    });
-    //Push newly created thread.
-    handles.push(handle);
-  }
-  drop(tx);
-  let mut count: i32 = 0;
-  
-  //Main thread waits for new pixels and assembles them to the framebuffer.
-  while let Ok(msg) = rx.recv() {
-    count = count + 1;
-    let (data, id) = msg;
-    framebuffer[id] = data;
-    print!("\r{:?}% of the image rendered.", (((count as f32)/((fheight*fwidth) as f32))*100.0) as i32);
-  }
-  
-  //Kill the threads once finished.
-  for handle in handles {
-    handle.join().unwrap();
+    else if target.is_none(){
+      break;
+    }
+    target = match target{
+      Some(t) if t < initial => Some(u32::min(t*2, initial)),
+      _ => None,
+    };
   }
   
   //Wrap everything up and send it to be output!
   let duration = start_time.elapsed();
-  println!("\nRendering completed in {} seconds.", duration.as_secs_f64());
-  let _ = framebuffer_to_ppm(&mut framebuffer, fwidth, fheight);
+  if interrupted(){
+    println!("Rendering interrupted after {} seconds.", duration.as_secs_f64());
+  }
+  else{
+    println!("Rendering completed in {} seconds.", duration.as_secs_f64());
+  }
+  return film;
+}
+
+//Resolves the film, denoises it and writes it to every output of the frame.
+//With a render region only that part is written when cropping, otherwise it sits in a black frame of the full size.
+fn write_outputs(film: &Film, scene: &Scene, settings: &Settings, frame: i32, animated: bool){
+  let (mut framebuffer, mut aovs) = film.resolve(scene.background_color);
+  let region = settings.region;
+  let mut region_framebuffer = region.crop(&framebuffer, settings.fwidth);
+  let region_aovs = region.crop(&aovs, settings.fwidth);
+  //Clean up the noise before anything gets tone mapped, only inside the region so the black around it doesn't bleed in.
+  if settings.denoise_strength > 0.0{
+    let start_time = Instant::now();
+    region_framebuffer = denoise(&region_framebuffer, &region_aovs, region.height(), region.width(), settings.denoise_strength, settings.denoise_iterations);
+    println!("Denoising completed in {} seconds.", start_time.elapsed().as_secs_f64());
+  }
+  let mut output_settings = settings.clone();
+  if settings.crop{
+    framebuffer = region_framebuffer;
+    aovs = region_aovs;
+    output_settings.fwidth = region.width();
+    output_settings.fheight = region.height();
+  }
+  else{
+    region.paste(&mut framebuffer, settings.fwidth, &region_framebuffer);
+  }
+  for output in &settings.outputs{
+    let filename = frame_filename(output, frame, animated);
+    if let Err(e) = write_framebuffer(&filename, &framebuffer, &aovs, &output_settings){
+      eprintln!("Failed to write {}: {}", filename, e);
+    }
+  }
+}
+
+//Loads every mesh file and writes its cache, so the first render using them starts quickly too.
+//"group=name,other" picks the OBJ groups of all the files, the same as on a mesh line.
+fn build_caches(args: &[String]) -> bool{
+  let groups: Vec<String> = args.iter().filter_map(|a| a.strip_prefix("group=")).flat_map(|g| g.split(',')).filter(|g| !g.is_empty()).map(|g| g.to_string()).collect();
+  let files: Vec<&String> = args.iter().filter(|a| !a.starts_with("group=")).collect();
+  if files.is_empty(){
+    eprintln!("Usage: rustracer cache <mesh files...> [group=name,other]");
+    return false;
+  }
+  let mut ok = true;
+  for file in files{
+    let start_time = Instant::now();
+    let asset = MeshAsset::new(load_mesh(file, &groups));
+    if asset.mesh.faces.is_empty(){
+      eprintln!("{} has no triangles, no cache written.", file);
+      ok = false;
+      continue;
+    }
+    match write_cache(file, &groups, &asset){
+      Ok(_) => println!("Wrote {} ({} triangles) in {} seconds.", cache_path(file), asset.mesh.faces.len(), start_time.elapsed().as_secs_f32()),
+      Err(e) => {
+        eprintln!("Failed to write the cache of {}: {}", file, e);
+        ok = false;
+      }
+    }
+  }
+  return ok;
 }
 
 fn main(){
   println!("Welcome to Rustracer!");
-  //Read in the user's script.
-  let mut input = String::new();
-  println!("Please enter the local path to your script file (example at 'scripts/house.rt'):");
-  io::stdout().flush().unwrap();
-  io::stdin().read_line(&mut input).expect("Failed to read your input.");
-  let input = input.trim().to_string();
+  //The script can be given on the command line, along with --resume to continue from the script's checkpoint.
+  let args: Vec<String> = env::args().skip(1).collect();
+  //"cache" builds the mesh caches of the files after it instead of rendering.
+  if args.first().map(|a| a.as_str()) == Some("cache"){
+    if !build_caches(&args[1..]){
+      process::exit(1);
+    }
+    return;
+  }
+  let resume = args.iter().any(|a| a == "--resume");
+  let input = match args.iter().find(|a| !a.starts_with("--")){
+    Some(path) => path.clone(),
+    None => {
+      //Read in the user's script.
+      let mut input = String::new();
+      println!("Please enter the local path to your script file (example at 'scripts/house.rt'):");
+      io::stdout().flush().unwrap();
+      io::stdin().read_line(&mut input).expect("Failed to read your input.");
+      input.trim().to_string()
+    }
+  };
+  let script = match fs::read_to_string(&input){
+    Ok(script) => script,
+    Err(e) => {
+      eprintln!("Failed to read your script {}: {}", input, e);
+      process::exit(1);
+    }
+  };
   //Interpret the script into our render variables.
-  let (lights, spheres, meshes, background_color, path_depth, fheight, fwidth, anti_alias) = interpreter(input);
+  let (mut scene, settings, animation) = interpreter(input);
+  let hash = scene_hash(&script, &settings.assets);
+  let checkpointing = !settings.checkpoint.is_empty();
+
+  //Pick up the frame the checkpoint was written for and the samples it already has.
+  let mut resumed: Option<(i32, Film)> = None;
+  if resume{
+    if !checkpointing{
+      eprintln!("Can't resume, the script doesn't set a checkpoint file (cp).");
+      process::exit(1);
+    }
+    match read_checkpoint(&settings.checkpoint, hash, &settings){
+      Ok(checkpoint) => {
+        println!("Resuming frame {} from {}.", checkpoint.0, settings.checkpoint);
+        resumed = Some(checkpoint);
+      }
+      Err(e) => {
+        eprintln!("Can't resume from {}: {}", settings.checkpoint, e);
+        process::exit(1);
+      }
+    }
+  }
+  let first_frame = resumed.as_ref().map_or(settings.frame_start, |r| r.0);
+
+  //Only progressive renders and checkpoints have something worth saving on Ctrl+C, otherwise it stops the renderer like it always did.
+  if settings.progressive || checkpointing{
+    interrupt::install();
+  }
   println!("Starting your render.");
-  //Begin the render!
-  render(&spheres, &lights, &meshes, background_color, path_depth, fheight, fwidth, anti_alias);
+  //Render every frame in the range, meshes are loaded once and only the animated properties change.
+  let animated = settings.frame_end > settings.frame_start;
+  for frame in first_frame..=settings.frame_end{
+    if !animation.is_empty(){
+      animation.apply(&mut scene, frame as f32);
+    }
+    if animated{
+      println!("Rendering frame {} of {}.", frame - settings.frame_start + 1, settings.frame_end - settings.frame_start + 1);
+    }
+    let film = match resumed.take(){
+      Some((_, film)) => film,
+      None => Film::new(settings.fwidth, settings.fheight),
+    };
+    let snapshot = |film: &Film| write_outputs(film, &scene, &settings, frame, animated);
+    let save = |film: &Film| match write_checkpoint(&settings.checkpoint, film, hash, &settings, frame){
+      Ok(_) => println!("Wrote checkpoint {}.", settings.checkpoint),
+      Err(e) => eprintln!("Failed to write checkpoint {}: {}", settings.checkpoint, e),
+    };
+    let mut periodic = vec![Periodic::new(settings.snapshot_interval, &snapshot)];
+    if checkpointing{
+      periodic.push(Periodic::new(settings.checkpoint_interval, &save));
+    }
+    //Begin the render!
+    let film = render(&scene, &settings, film, &mut periodic);
+    //Save once more at the end, so a render stopped with Ctrl+C loses nothing.
+    if checkpointing{
+      save(&film);
+    }
+    write_outputs(&film, &scene, &settings, frame, animated);
+    //Ctrl+C stops the whole animation, not just this frame.
+    if interrupted(){
+      break;
+    }
+  }
 }
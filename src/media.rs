@@ -0,0 +1,213 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Light;
+use definitions::Ray;
+
+//Import the shared meshes from instance.rs
+use instance::MeshAsset;
+
+//Import the triangle intersection from intersect.rs
+use intersect::triangle_intersect;
+
+//Import the integer hash from random.rs
+use random::hash;
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+//Fog ends where less than this much light gets through (about 0.1%), so rays into an empty sky don't march forever.
+const FOG_OPTICAL_DEPTH: f32 = 7.0;
+
+//A homogeneous participating medium.
+//Density is how much light it takes out per unit of distance, color is how much of that is scattered instead of absorbed,
+//and anisotropy is the Henyey-Greenstein g (0 scatters evenly, towards 1 scatters forward like haze, towards -1 back).
+#[derive(Debug, Copy, Clone)]
+pub struct Medium{
+  pub density: f32,
+  pub color: Vector3,
+  pub anisotropy: f32,
+}
+
+impl Medium{
+
+  pub fn new(density: f32, color: Vector3, anisotropy: f32) -> Self {
+    Medium {density, color, anisotropy: anisotropy.max(-0.99).min(0.99)}
+  }
+
+  //The Henyey-Greenstein phase function, cosine is between where the light goes and where the ray goes.
+  pub fn phase(&self, cosine: f32) -> f32 {
+    let g = self.anisotropy;
+    let denominator = 1.0 + g*g - 2.0*g*cosine;
+    (1.0 - g*g)/(4.0*PI*denominator*denominator.sqrt())
+  }
+
+}
+
+//Where a bounded volume is, its surface isn't drawn, it only holds the medium.
+#[derive(Debug, Clone)]
+pub enum VolumeShape{
+  Sphere(Vector3, f32),
+  //A closed mesh moved by an offset, open meshes give odd results since inside and outside aren't clear.
+  Mesh(Arc<MeshAsset>, Vector3),
+}
+
+#[derive(Debug, Clone)]
+pub struct Volume{
+  pub shape: VolumeShape,
+  pub medium: Medium,
+}
+
+impl Volume{
+
+  //The parts of the ray between 0 and t_max that are inside the volume.
+  fn intervals(&self, ray: Ray, t_max: f32) -> Vec<(f32, f32)> {
+    let mut crossings: Vec<f32> = Vec::new();
+    let mut inside = false;
+    match &self.shape {
+      VolumeShape::Sphere(center, radius) => {
+        let to_center = *center - ray.origin;
+        let along = to_center.dot(&ray.direction);
+        let squared = to_center.dot(&to_center) - along*along;
+        if squared > radius*radius {
+          return Vec::new();
+        }
+        let half = (radius*radius - squared).sqrt();
+        crossings.extend_from_slice(&[along - half, along + half]);
+      },
+      VolumeShape::Mesh(asset, offset) => {
+        let local = Ray::new(ray.origin - *offset, ray.direction, ray.time);
+        let mesh = &asset.mesh;
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        //Every triangle the whole ray goes through, the test never gives a distance back so no box is skipped.
+        asset.bvh.traverse(local, f32::MAX, |i| {
+          let f = mesh.faces[i];
+          if let Some((t, _, _)) = triangle_intersect(local, mesh.verts[f.x as usize], mesh.verts[f.y as usize], mesh.verts[f.z as usize], zero) {
+            crossings.push(t);
+          }
+          None
+        });
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        //An odd number of crossings ahead means the ray starts inside.
+        inside = crossings.len() % 2 == 1;
+      },
+    }
+    let mut intervals: Vec<(f32, f32)> = Vec::new();
+    let mut start = 0.0;
+    for &t in &crossings {
+      if t > 0.0 {
+        if inside {
+          intervals.push((start, t.min(t_max)));
+        }
+        start = t.max(0.0);
+      }
+      inside = !inside;
+      if t >= t_max {
+        break;
+      }
+    }
+    if inside && start < t_max {
+      intervals.push((start, t_max));
+    }
+    return intervals.into_iter().filter(|(a, b)| b > a).collect();
+  }
+
+}
+
+//Everything the rays travel through between surfaces: fog all over the scene and volumes in some places.
+#[derive(Debug, Clone)]
+pub struct Media{
+  pub fog: Option<Medium>,
+  pub volumes: Vec<Volume>,
+  //How many points every stretch of a medium is sampled at for the light scattered towards the camera.
+  pub steps: u32,
+}
+
+//A stretch of a ray inside one medium.
+struct Segment{
+  start: f32,
+  end: f32,
+  medium: Medium,
+}
+
+impl Media{
+
+  pub fn new() -> Self {
+    Media {fog: None, volumes: Vec::new(), steps: 32}
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.fog().is_none() && self.volumes.is_empty()
+  }
+
+  //Fog that doesn't take any light out is the same as no fog.
+  fn fog(&self) -> Option<Medium> {
+    self.fog.filter(|fog| fog.density > 0.0)
+  }
+
+  //The stretches of the ray (up to t_max) that are inside a medium, overlapping media each get their own.
+  fn segments(&self, ray: Ray, t_max: f32) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+    if let Some(fog) = self.fog() {
+      let end = if t_max.is_finite() {t_max} else {FOG_OPTICAL_DEPTH/fog.density};
+      segments.push(Segment {start: 0.0, end, medium: fog});
+    }
+    //Volumes are closed, so a ray that hits nothing is only followed as far as the largest float.
+    let t_max = if t_max.is_finite() {t_max} else {f32::MAX};
+    for volume in &self.volumes {
+      for (start, end) in volume.intervals(ray, t_max) {
+        segments.push(Segment {start, end, medium: volume.medium});
+      }
+    }
+    return segments;
+  }
+
+  //How much of the light makes it along the ray from its origin to t, between 0 and 1.
+  //Fog goes on forever, so nothing gets through it from infinitely far away.
+  pub fn transmittance(&self, ray: Ray, t: f32) -> f32 {
+    if self.is_empty() {
+      return 1.0;
+    }
+    return self.transmittance_through(&self.segments(ray, t), t);
+  }
+
+  fn transmittance_through(&self, segments: &Vec<Segment>, t: f32) -> f32 {
+    if !t.is_finite() && self.fog().is_some() {
+      return 0.0;
+    }
+    return (-segments.iter().map(|s| s.medium.density*(t.min(s.end) - s.start).max(0.0)).sum::<f32>()).exp();
+  }
+
+  //Dims what the ray sees at distance t (infinite when it hit nothing) and adds the light the media scatter towards it.
+  //Only single scattering is done: every sample point is lit straight from the lights, visible tells if a light can be seen from a point.
+  pub fn apply(&self, ray: Ray, t: f32, color: Vector3, lights: &Vec<Light>, visible: &dyn Fn(Vector3, &Light) -> bool) -> Vector3 {
+    if self.is_empty() {
+      return color;
+    }
+    let segments = self.segments(ray, t);
+    //The samples are shifted by a different amount for every ray, which trades the banding of fixed steps for noise that anti-aliasing averages out.
+    let jitter = (hash(ray.direction.x.to_bits() ^ hash(ray.direction.y.to_bits() ^ hash(ray.direction.z.to_bits() ^ hash(ray.origin.x.to_bits())))) >> 8) as f32/16777216.0;
+    let mut scattered = Vector3::new(0.0, 0.0, 0.0);
+    for segment in &segments {
+      let step = (segment.end - segment.start)/self.steps as f32;
+      for i in 0..self.steps {
+        let along = segment.start + (i as f32 + jitter)*step;
+        let point = ray.origin + ray.direction*along;
+        let mut light = 0.0;
+        for l in lights {
+          let to_light = l.transform - point;
+          let distance = to_light.magnitude();
+          let direction = to_light*(1.0/distance);
+          if !visible(point, l) {
+            continue;
+          }
+          let towards = Ray::new(point, direction, ray.time);
+          light += l.intensity*segment.medium.phase(direction.dot(&ray.direction))*self.transmittance(towards, distance);
+        }
+        let weight = self.transmittance_through(&segments, along)*segment.medium.density*step*light;
+        scattered = scattered + segment.medium.color*weight;
+      }
+    }
+    return color*self.transmittance_through(&segments, t) + scattered;
+  }
+
+}
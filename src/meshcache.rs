@@ -0,0 +1,196 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Vector3i;
+use definitions::Vector2;
+use definitions::Vector4;
+
+//Import the mesh data from model.rs
+use model::MeshData;
+
+//Import the shared meshes from instance.rs
+use instance::MeshAsset;
+
+//Import the acceleration structure from bvh.rs
+use bvh::{Aabb, Bvh, BvhNode};
+
+//Import the hashing and reading helpers from checkpoint.rs
+use checkpoint::{hash_bytes, read_u32, read_u64, invalid};
+
+//Import all of the standard libraries we need.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write, Seek, SeekFrom, BufReader, BufWriter};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 4] = b"RTMC";
+const VERSION: u32 = 3;
+
+//The cache sits next to the mesh, "house.obj" is cached in "house.obj.cache".
+pub fn cache_path(filename: &str) -> String{
+  format!("{}.cache", filename)
+}
+
+//What we know about the source file, so a cache is only used for the file it was made from.
+struct Source{
+  size: u64,
+  modified: u64,
+  hash: u64,
+}
+
+fn source(filename: &str, with_hash: bool) -> io::Result<Source>{
+  let metadata = fs::metadata(filename)?;
+  let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+  let hash = if with_hash {hash_bytes(&fs::read(filename)?)} else {0};
+  Ok(Source {size: metadata.len(), modified, hash})
+}
+
+//Writes the mesh with its BVH, so loading it again is just reading the numbers back.
+//Like checkpoints, it goes to a temporary file first that is renamed over the cache.
+pub fn write_cache(filename: &str, groups: &Vec<String>, asset: &MeshAsset) -> io::Result<()>{
+  let source = source(filename, true)?;
+  let path = cache_path(filename);
+  let temporary = format!("{}.tmp", path);
+  {
+    let mut file = BufWriter::new(File::create(&temporary)?);
+    file.write_all(MAGIC)?;
+    file.write_all(&VERSION.to_le_bytes())?;
+    file.write_all(&source.size.to_le_bytes())?;
+    file.write_all(&source.modified.to_le_bytes())?;
+    file.write_all(&source.hash.to_le_bytes())?;
+    let groups = groups.join(",");
+    file.write_all(&(groups.len() as u32).to_le_bytes())?;
+    file.write_all(groups.as_bytes())?;
+
+    let mesh = &asset.mesh;
+    write_vectors(&mut file, &mesh.verts)?;
+    write_vectors(&mut file, &mesh.normals)?;
+    write_vectors(&mut file, &mesh.colors)?;
+    file.write_all(&(mesh.uvs.len() as u32).to_le_bytes())?;
+    for uv in &mesh.uvs {
+      write_floats(&mut file, &[uv.x, uv.y])?;
+    }
+    file.write_all(&(mesh.tangents.len() as u32).to_le_bytes())?;
+    for t in &mesh.tangents {
+      write_floats(&mut file, &[t.x, t.y, t.z, t.a])?;
+    }
+    file.write_all(&(mesh.faces.len() as u32).to_le_bytes())?;
+    for face in &mesh.faces {
+      for i in [face.x, face.y, face.z].iter() {
+        file.write_all(&i.to_le_bytes())?;
+      }
+    }
+
+    write_floats(&mut file, &bounds_floats(&asset.bounds))?;
+    file.write_all(&(asset.bvh.nodes.len() as u32).to_le_bytes())?;
+    for node in &asset.bvh.nodes {
+      write_floats(&mut file, &bounds_floats(&node.bounds))?;
+      file.write_all(&(node.first as u32).to_le_bytes())?;
+      file.write_all(&(node.count as u32).to_le_bytes())?;
+    }
+    file.write_all(&(asset.bvh.indices.len() as u32).to_le_bytes())?;
+    for i in &asset.bvh.indices {
+      file.write_all(&(*i as u32).to_le_bytes())?;
+    }
+    file.flush()?;
+  }
+  fs::rename(&temporary, &path)
+}
+
+//Loads a cache, refusing it when the mesh file has changed or it was made for other groups.
+//The modification time and size are checked first, the file is only hashed when they differ (after a copy or a checkout).
+//When the hash still matches, the new time and size are written back so the next load doesn't hash it again.
+pub fn read_cache(filename: &str, groups: &Vec<String>) -> io::Result<MeshAsset>{
+  let mut file = BufReader::new(File::open(cache_path(filename))?);
+  let mut magic = [0u8; 4];
+  file.read_exact(&mut magic)?;
+  if &magic != MAGIC || read_u32(&mut file)? != VERSION {
+    return Err(invalid("not a mesh cache written by this version of the renderer"));
+  }
+  let (size, modified, hash) = (read_u64(&mut file)?, read_u64(&mut file)?, read_u64(&mut file)?);
+  let current = source(filename, false)?;
+  let moved = current.size != size || current.modified != modified;
+  if moved && source(filename, true)?.hash != hash {
+    return Err(invalid("the mesh has changed since the cache was written"));
+  }
+  let length = read_u32(&mut file)? as usize;
+  if length > 1 << 16 {
+    return Err(invalid("the cache is damaged"));
+  }
+  let mut cached_groups = vec![0u8; length];
+  file.read_exact(&mut cached_groups)?;
+  if cached_groups != groups.join(",").into_bytes() {
+    return Err(invalid("the cache was written for other groups"));
+  }
+
+  let mut mesh = MeshData::new(read_vectors(&mut file)?, Vec::new());
+  mesh.normals = read_vectors(&mut file)?;
+  mesh.colors = read_vectors(&mut file)?;
+  let count = read_u32(&mut file)? as usize;
+  mesh.uvs = (0..count).map(|_| read_floats(&mut file, 2).map(|f| Vector2::new(f[0], f[1]))).collect::<io::Result<_>>()?;
+  let count = read_u32(&mut file)? as usize;
+  mesh.tangents = (0..count).map(|_| read_floats(&mut file, 4).map(|f| Vector4::new(f[0], f[1], f[2], f[3]))).collect::<io::Result<_>>()?;
+  let count = read_u32(&mut file)? as usize;
+  mesh.faces = (0..count).map(|_| Ok(Vector3i::new(read_u32(&mut file)? as i32, read_u32(&mut file)? as i32, read_u32(&mut file)? as i32))).collect::<io::Result<_>>()?;
+
+  let bounds = read_bounds(&mut file)?;
+  let count = read_u32(&mut file)? as usize;
+  let nodes = (0..count).map(|_| Ok(BvhNode {bounds: read_bounds(&mut file)?, first: read_u32(&mut file)? as usize, count: read_u32(&mut file)? as usize})).collect::<io::Result<Vec<BvhNode>>>()?;
+  let count = read_u32(&mut file)? as usize;
+  let indices = (0..count).map(|_| read_u32(&mut file).map(|i| i as usize)).collect::<io::Result<Vec<usize>>>()?;
+
+  //A cache that points outside of itself is broken, it's rebuilt instead of crashing the render.
+  let vertices = mesh.verts.len() as i32;
+  let per_vertex = |count: usize| count == 0 || count == mesh.verts.len();
+  if !per_vertex(mesh.normals.len()) || !per_vertex(mesh.colors.len()) || !per_vertex(mesh.uvs.len()) || !per_vertex(mesh.tangents.len())
+    || mesh.faces.iter().any(|f| f.x < 0 || f.y < 0 || f.z < 0 || f.x >= vertices || f.y >= vertices || f.z >= vertices)
+    || indices.iter().any(|&i| i >= mesh.faces.len())
+    || nodes.iter().any(|n| if n.count == 0 {n.first + 1 >= nodes.len()} else {n.first + n.count > indices.len()}) {
+    return Err(invalid("the cache is damaged"));
+  }
+  if moved {
+    //A cache we can't write to still loads, it's just hashed again next time.
+    let _ = refresh_source(filename, &current);
+  }
+  Ok(MeshAsset {mesh, bounds, bvh: Bvh {nodes, indices}})
+}
+
+//Overwrites the size and modification time in the header of a cache, they come right after the magic and version.
+fn refresh_source(filename: &str, current: &Source) -> io::Result<()>{
+  let mut file = OpenOptions::new().write(true).open(cache_path(filename))?;
+  file.seek(SeekFrom::Start(8))?;
+  file.write_all(&current.size.to_le_bytes())?;
+  file.write_all(&current.modified.to_le_bytes())?;
+  Ok(())
+}
+
+fn write_floats(file: &mut dyn Write, floats: &[f32]) -> io::Result<()>{
+  for f in floats {
+    file.write_all(&f.to_le_bytes())?;
+  }
+  Ok(())
+}
+
+fn write_vectors(file: &mut dyn Write, vectors: &Vec<Vector3>) -> io::Result<()>{
+  file.write_all(&(vectors.len() as u32).to_le_bytes())?;
+  for v in vectors {
+    write_floats(file, &[v.x, v.y, v.z])?;
+  }
+  Ok(())
+}
+
+fn bounds_floats(bounds: &Aabb) -> [f32; 6]{
+  [bounds.min.x, bounds.min.y, bounds.min.z, bounds.max.x, bounds.max.y, bounds.max.z]
+}
+
+fn read_floats(file: &mut dyn Read, count: usize) -> io::Result<Vec<f32>>{
+  (0..count).map(|_| read_u32(file).map(f32::from_bits)).collect()
+}
+
+fn read_vectors(file: &mut dyn Read) -> io::Result<Vec<Vector3>>{
+  let count = read_u32(file)? as usize;
+  (0..count).map(|_| read_floats(file, 3).map(|f| Vector3::new(f[0], f[1], f[2]))).collect()
+}
+
+fn read_bounds(file: &mut dyn Read) -> io::Result<Aabb>{
+  let f = read_floats(file, 6)?;
+  Ok(Aabb {min: Vector3::new(f[0], f[1], f[2]), max: Vector3::new(f[3], f[4], f[5])})
+}
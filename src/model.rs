@@ -1,63 +1,427 @@
 use definitions::Vector3;
 use definitions::Vector3i;
+use definitions::Vector2;
+use definitions::Vector4;
 use definitions::Material;
+use definitions::lerp;
 
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+//Import the PLY loader from ply.rs
+use ply::load_ply;
+
+//Import the STL loader from stl.rs
+use stl::load_stl;
+
+//Import the shared meshes from instance.rs
+use instance::MeshAsset;
+
+//Import the textures and the normal and bump maps from texture.rs
+use texture::{Texture, detail_normal};
+
+use std::collections::HashMap;
+use std::fs;
 use std::path::Path;
+use std::sync::Arc;
+
+//Displaced surfaces keep a hard edge where they fold sharper than this (in degrees), like the sides of a raised brick.
+const DISPLACED_CREASE_ANGLE: f32 = 60.0;
 
+//The triangles of a mesh file, normals, UVs and colors are empty when the file doesn't have them (otherwise there's one per vertex).
+//Tangents are made from the UVs for normal and bump maps, they point along u and a is the direction of v (1 or -1, mirrored UVs flip it).
 #[derive(Debug, Clone)]
-pub struct Model{
+pub struct MeshData{
   pub verts: Vec<Vector3>,
   pub faces: Vec<Vector3i>,
+  pub normals: Vec<Vector3>,
+  pub uvs: Vec<Vector2>,
+  pub colors: Vec<Vector3>,
+  pub tangents: Vec<Vector4>,
+}
+
+impl MeshData{
+
+  pub fn new(verts: Vec<Vector3>, faces: Vec<Vector3i>) -> Self {
+    MeshData {verts, faces, normals: Vec::new(), uvs: Vec::new(), colors: Vec::new(), tangents: Vec::new()}
+  }
+
+  //Turns a hit on one of the triangles (with the barycentric UVs from triangle_intersect) into what gets shaded.
+  //Vertex normals and UVs are blended across the triangle and vertex colors replace the diffuse color of the material.
+  pub fn surface(&self, face: usize, hit: (f32, Vector3, Vector2), material: &Material) -> (f32, Vector3, Vector2, Option<Vector4>, Material) {
+    let (t, mut normal, mut uv) = hit;
+    let mut material = material.clone();
+    let f = self.faces[face];
+    let (a, b, c) = (f.x as usize, f.y as usize, f.z as usize);
+    let weights = (1.0 - hit.2.x - hit.2.y, hit.2.x, hit.2.y);
+    if !self.normals.is_empty() {
+      normal = (self.normals[a]*weights.0 + self.normals[b]*weights.1 + self.normals[c]*weights.2).normalize();
+    }
+    if !self.uvs.is_empty() {
+      uv = Vector2::new(
+        self.uvs[a].x*weights.0 + self.uvs[b].x*weights.1 + self.uvs[c].x*weights.2,
+        self.uvs[a].y*weights.0 + self.uvs[b].y*weights.1 + self.uvs[c].y*weights.2,
+      );
+    }
+    if !self.colors.is_empty() {
+      material.diffuse_color = self.colors[a]*weights.0 + self.colors[b]*weights.1 + self.colors[c]*weights.2;
+    }
+    let tangent = if self.tangents.is_empty() {None} else {
+      let (ta, tb, tc) = (self.tangents[a], self.tangents[b], self.tangents[c]);
+      Some(Vector4::new(ta.x*weights.0 + tb.x*weights.1 + tc.x*weights.2, ta.y*weights.0 + tb.y*weights.1 + tc.y*weights.2, ta.z*weights.0 + tb.z*weights.1 + tc.z*weights.2, ta.a))
+    };
+    normal = detail_normal(&material, normal, tangent, uv);
+    return (t, normal, uv, tangent, material);
+  }
+
+  //Makes a tangent for every vertex from how the UVs run across the faces around it.
+  //Meshes without UVs don't get any, meshes without normals build them around the faces the vertex is on.
+  pub fn compute_tangents(&mut self) {
+    self.tangents.clear();
+    if self.uvs.is_empty() {
+      return;
+    }
+    let zero = Vector3::new(0.0, 0.0, 0.0);
+    let mut along_u = vec![zero; self.verts.len()];
+    let mut along_v = vec![zero; self.verts.len()];
+    let mut face_normals = vec![zero; self.verts.len()];
+    for f in &self.faces {
+      let (a, b, c) = (f.x as usize, f.y as usize, f.z as usize);
+      let (edge1, edge2) = (self.verts[b] - self.verts[a], self.verts[c] - self.verts[a]);
+      let face_normal = edge1.cross(&edge2);
+      for &i in [a, b, c].iter() {
+        face_normals[i] = face_normals[i] + face_normal;
+      }
+      let (du1, dv1) = (self.uvs[b].x - self.uvs[a].x, self.uvs[b].y - self.uvs[a].y);
+      let (du2, dv2) = (self.uvs[c].x - self.uvs[a].x, self.uvs[c].y - self.uvs[a].y);
+      let determinant = du1*dv2 - du2*dv1;
+      if determinant.abs() < 1e-12 {
+        continue;
+      }
+      //Bigger faces count more, so the sums aren't divided by the face's area.
+      let sign = determinant.signum();
+      let u = (edge1*dv2 - edge2*dv1)*sign;
+      let v = (edge2*du1 - edge1*du2)*sign;
+      for &i in [a, b, c].iter() {
+        along_u[i] = along_u[i] + u;
+        along_v[i] = along_v[i] + v;
+      }
+    }
+    self.tangents = (0..self.verts.len()).map(|i| {
+      let n = if self.normals.is_empty() {face_normals[i].normalize()} else {self.normals[i]};
+      let t = (along_u[i] - n*n.dot(&along_u[i])).normalize();
+      let handedness = if n.cross(&t).dot(&along_v[i]) < 0.0 {-1.0} else {1.0};
+      Vector4::new(t.x, t.y, t.z, handedness)
+    }).collect();
+  }
+
+  //Moves every vertex along its normal by the height map at its UV (black stays put, white moves by depth).
+  //It needs a finely subdivided mesh to have vertices to move, the normals are made again from the new shape.
+  //Creases and UV seams split a vertex into copies with their own normal and UV, all copies of a position move
+  //by the average of their normals and heights so the surface doesn't tear open along them.
+  pub fn displace(&mut self, map: &Texture, depth: f32) -> Result<(), String> {
+    if self.uvs.is_empty() {
+      return Err("the mesh has no UVs to read the height map with".to_string());
+    }
+    if self.normals.is_empty() {
+      self.smooth_normals(180.0);
+    }
+    let mut copies: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+    for (i, v) in self.verts.iter().enumerate() {
+      copies.entry([v.x.to_bits(), v.y.to_bits(), v.z.to_bits()]).or_insert_with(Vec::new).push(i);
+    }
+    for indices in copies.values() {
+      let zero = Vector3::new(0.0, 0.0, 0.0);
+      let normal = indices.iter().fold(zero, |sum, &i| sum + self.normals[i]).normalize();
+      let height = indices.iter().map(|&i| map.height_at(self.uvs[i])).sum::<f32>()/indices.len() as f32;
+      for &i in indices {
+        self.verts[i] = self.verts[i] + normal*(height*depth);
+      }
+    }
+    self.smooth_normals(DISPLACED_CREASE_ANGLE);
+    return Ok(());
+  }
+
+  //Makes vertex normals by averaging the faces around every vertex (bigger faces count more).
+  //Faces meeting at more than the crease angle (in degrees) stay sharp, their corners get their own copy of the vertex.
+  pub fn smooth_normals(&mut self, crease_angle: f32) {
+    let face_normals: Vec<Vector3> = self.faces.iter().map(|f| {
+      let (a, b, c) = (self.verts[f.x as usize], self.verts[f.y as usize], self.verts[f.z as usize]);
+      (b - a).cross(&(c - a))
+    }).collect();
+    let mut vertex_faces: Vec<Vec<usize>> = vec![Vec::new(); self.verts.len()];
+    for (i, f) in self.faces.iter().enumerate() {
+      vertex_faces[f.x as usize].push(i);
+      vertex_faces[f.y as usize].push(i);
+      vertex_faces[f.z as usize].push(i);
+    }
+    let threshold = crease_angle.to_radians().cos();
+
+    let mut mesh = MeshData::new(Vec::new(), Vec::new());
+    //The same vertex with the same normal is only stored once.
+    let mut corners: HashMap<(usize, [u32; 3]), i32> = HashMap::new();
+    for (i, f) in self.faces.iter().enumerate() {
+      let own = face_normals[i].normalize();
+      let mut face = [0; 3];
+      for (k, &v) in [f.x as usize, f.y as usize, f.z as usize].iter().enumerate() {
+        let mut sum = Vector3::new(0.0, 0.0, 0.0);
+        for &g in &vertex_faces[v] {
+          if g == i || face_normals[g].normalize().dot(&own) >= threshold {
+            sum = sum + face_normals[g];
+          }
+        }
+        let normal = sum.normalize();
+        let key = (v, [normal.x.to_bits(), normal.y.to_bits(), normal.z.to_bits()]);
+        face[k] = *corners.entry(key).or_insert_with(|| {
+          mesh.verts.push(self.verts[v]);
+          mesh.normals.push(normal);
+          if !self.uvs.is_empty() {
+            mesh.uvs.push(self.uvs[v]);
+          }
+          if !self.colors.is_empty() {
+            mesh.colors.push(self.colors[v]);
+          }
+          if !self.tangents.is_empty() {
+            mesh.tangents.push(self.tangents[v]);
+          }
+          mesh.verts.len() as i32 - 1
+        });
+      }
+      mesh.faces.push(Vector3i::new(face[0], face[1], face[2]));
+    }
+    *self = mesh;
+  }
+
+}
+
+#[derive(Debug, Clone)]
+pub struct Model{
+  pub asset: Arc<MeshAsset>,
   pub transform: Vector3,
+  pub transform_end: Vector3,
   pub material: Material,
+  pub id: u32,
 }
 
 impl Model{
-  pub fn new(filename: &str, transform: Vector3, material: Material) -> Self {
-    let mut verts: Vec<Vector3> = Vec::new();
-    let mut faces: Vec<Vector3i> = Vec::new();
-    let path = Path::new(filename);
-    let file = match File::open(path) {
-      Ok(file) => BufReader::new(file),
-      Err(err) => {
-        eprintln!("Failed to open {}: {}", filename, err);
-        return Model {verts, faces, transform, material};
-      }
+  pub fn new(asset: Arc<MeshAsset>, transform: Vector3, transform_end: Vector3, material: Material, id: u32) -> Self {
+    return Model {asset, transform, transform_end, material, id};
+  }
+
+  pub fn from_mesh(mesh: MeshData, transform: Vector3, transform_end: Vector3, material: Material, id: u32) -> Self {
+    return Model {asset: Arc::new(MeshAsset::new(mesh)), transform, transform_end, material, id};
+  }
+
+  //Position of the model at a point in the frame (0.0 = start, 1.0 = end).
+  pub fn transform_at(&self, time: f32) -> Vector3 {
+    lerp(self.transform, self.transform_end, time)
+  }
+
+}
+
+//Picks the loader from the file extension, anything we don't know is read as OBJ.
+//Groups pick the objects and groups of an OBJ file by name, an empty list loads everything.
+pub fn load_mesh(filename: &str, groups: &Vec<String>) -> MeshData {
+  let extension = Path::new(filename).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+  if !groups.is_empty() && extension != "obj" {
+    eprintln!("Only OBJ files have groups, loading all of {}.", filename);
+  }
+  let loaded = match extension.as_str() {
+    "ply" => load_ply(filename),
+    "stl" => load_stl(filename),
+    _ => load_obj(filename, groups),
+  };
+  match loaded {
+    Ok(mesh) => mesh,
+    Err(err) => {
+      eprintln!("Failed to load {}: {}", filename, err);
+      MeshData::new(Vec::new(), Vec::new())
+    }
+  }
+}
+
+//One corner of an OBJ face: the position, UV and normal it uses (counting from 0).
+pub type Corner = (usize, Option<usize>, Option<usize>);
+
+//What an OBJ file holds before its polygons are split into triangles, with the smoothing group of every polygon (0 when off).
+pub struct ObjData{
+  pub positions: Vec<Vector3>,
+  pub colors: Vec<Vector3>,
+  pub uvs: Vec<Vector2>,
+  pub normals: Vec<Vector3>,
+  pub polygons: Vec<(Vec<Corner>, u32)>,
+}
+
+//Reads the statements of an OBJ file, only keeping the faces of the groups asked for.
+pub fn parse_obj(filename: &str, groups: &Vec<String>) -> Result<ObjData, String> {
+  let data = fs::read(filename).map_err(|e| e.to_string())?;
+  let text = String::from_utf8_lossy(&data);
+
+  let mut positions: Vec<Vector3> = Vec::new();
+  let mut colors: Vec<Vector3> = Vec::new();
+  let mut uvs: Vec<Vector2> = Vec::new();
+  let mut normals: Vec<Vector3> = Vec::new();
+  let mut polygons: Vec<(Vec<Corner>, u32)> = Vec::new();
+  let mut smoothing = 0;
+  //The names every face was made under, "o" sets the object and "g" the groups (a face can be in several).
+  let mut object = String::new();
+  let mut group_names: Vec<String> = Vec::new();
+  let mut found: Vec<String> = Vec::new();
+  let selected = |object: &String, group_names: &Vec<String>| groups.is_empty() || groups.contains(object) || group_names.iter().any(|g| groups.contains(g));
+
+  let mut statement = String::new();
+  let mut start = 0;
+  for (i, line) in text.lines().enumerate() {
+    if statement.is_empty() {
+      start = i + 1;
+    }
+    //A backslash at the end carries the statement on to the next line.
+    let line = line.split('#').next().unwrap_or("");
+    if line.trim_end().ends_with('\\') {
+      statement.push_str(&line.trim_end()[..line.trim_end().len() - 1]);
+      statement.push(' ');
+      continue;
+    }
+    statement.push_str(line);
+    let parts: Vec<&str> = statement.split_whitespace().collect();
+    let error = |message: String| format!("line {}: {}", start, message);
+    let numbers = |from: usize| -> Result<Vec<f32>, String> {
+      parts[from..].iter().map(|p| p.parse::<f32>().map_err(|_| error(format!("expected a number but found '{}'", p)))).collect()
     };
-    let reader = BufReader::new(file);
-
-  for line in reader.lines() {
-      let line = line.unwrap();
-      let parts: Vec<&str> = line.trim().split_whitespace().collect();
-      if parts.is_empty() { continue; }
-
-      match parts[0] {
-          "v" => {
-              let x: f32 = parts[1].parse().unwrap();
-              let y: f32 = parts[2].parse().unwrap();
-              let z: f32 = parts[3].parse().unwrap();
-              verts.push(Vector3::new(x, y, z));
-          },
-          "f" => {
-              let mut indices = Vec::new();
-              for part in &parts[1..] {
-                  let index_part = part.split('/').next().unwrap();
-                  let index = index_part.parse::<i32>().unwrap() - 1;
-                  indices.push(index);
-              }
-              if indices.len() >= 3 {
-                  for i in 2..indices.len() {
-                      faces.push(Vector3i::new(indices[0], indices[i-1], indices[i]));
-                  }
-              }
+
+    match parts.first().map(|p| *p) {
+      Some("v") => {
+        let v = numbers(1)?;
+        match v.len() {
+          //x y z with an optional w, which only matters for curves.
+          3 | 4 => positions.push(Vector3::new(v[0], v[1], v[2])),
+          //Some programs write vertex colors after the position.
+          6 | 7 => {
+            positions.push(Vector3::new(v[0], v[1], v[2]));
+            colors.resize(positions.len() - 1, Vector3::new(1.0, 1.0, 1.0));
+            colors.push(Vector3::new(v[v.len() - 3], v[v.len() - 2], v[v.len() - 1]));
           },
-          _ => {}
-      }
+          _ => return Err(error("a vertex needs x, y and z".to_string())),
+        }
+      },
+      Some("vt") => {
+        let t = numbers(1)?;
+        if t.is_empty() || t.len() > 3 {
+          return Err(error("a texture coordinate needs u and optionally v and w".to_string()));
+        }
+        uvs.push(Vector2::new(t[0], *t.get(1).unwrap_or(&0.0)));
+      },
+      Some("vn") => {
+        let n = numbers(1)?;
+        if n.len() != 3 {
+          return Err(error("a normal needs x, y and z".to_string()));
+        }
+        normals.push(Vector3::new(n[0], n[1], n[2]).normalize());
+      },
+      Some("f") => {
+        if parts.len() < 4 {
+          return Err(error("a face needs at least 3 vertices".to_string()));
+        }
+        let mut corners: Vec<Corner> = Vec::new();
+        for part in &parts[1..] {
+          let indices: Vec<&str> = part.split('/').collect();
+          if indices.len() > 3 {
+            return Err(error(format!("can't read the face vertex '{}'", part)));
+          }
+          let position = obj_index(indices[0], positions.len(), "vertex").map_err(&error)?;
+          let uv = match indices.get(1) {
+            Some(t) if !t.is_empty() => Some(obj_index(t, uvs.len(), "texture coordinate").map_err(&error)?),
+            _ => None,
+          };
+          let normal = match indices.get(2) {
+            Some(n) if !n.is_empty() => Some(obj_index(n, normals.len(), "normal").map_err(&error)?),
+            _ => None,
+          };
+          corners.push((position, uv, normal));
+        }
+        if selected(&object, &group_names) {
+          polygons.push((corners, smoothing));
+        }
+      },
+      Some("o") => {
+        object = parts[1..].join(" ");
+        found.push(object.clone());
+      },
+      Some("g") => {
+        group_names = parts[1..].iter().map(|g| g.to_string()).collect();
+        found.extend(group_names.iter().cloned());
+      },
+      //Smoothing groups only matter for subdivision, edges between different groups stay sharp.
+      Some("s") => smoothing = match parts.get(1) {
+        Some(&"off") | None => 0,
+        Some(n) => n.parse().map_err(|_| error(format!("bad smoothing group '{}'", n)))?,
+      },
+      //Materials, lines, points and curves don't matter for rendering triangles.
+      _ => (),
     }
-    return Model {verts, faces, transform, material};
+    statement.clear();
   }
+  if !statement.is_empty() {
+    return Err(format!("line {}: the file ends in the middle of a statement", start));
+  }
+  for group in groups {
+    if !found.contains(group) {
+      return Err(format!("there's no object or group called '{}'", group));
+    }
+  }
+  if positions.len() > colors.len() && !colors.is_empty() {
+    colors.resize(positions.len(), Vector3::new(1.0, 1.0, 1.0));
+  }
+  return Ok(ObjData {positions, colors, uvs, normals, polygons});
+}
 
+//Reads a Wavefront OBJ file, polygons are split into triangles.
+//Indices can count back from the end (-1 is the last vertex so far), and faces that give their corners different UVs or normals get their own vertices.
+pub fn load_obj(filename: &str, groups: &Vec<String>) -> Result<MeshData, String> {
+  let ObjData {positions, colors, uvs, normals, polygons} = parse_obj(filename, groups)?;
+  let mut triangles: Vec<[Corner; 3]> = Vec::new();
+  for (corners, _) in &polygons {
+    for i in 2..corners.len() {
+      triangles.push([corners[0], corners[i - 1], corners[i]]);
+    }
+  }
+
+  //UVs and normals are only used when every corner has one, otherwise the blend across a triangle wouldn't make sense.
+  let with_uvs = !triangles.is_empty() && triangles.iter().all(|t| t.iter().all(|c| c.1.is_some()));
+  let with_normals = !triangles.is_empty() && triangles.iter().all(|t| t.iter().all(|c| c.2.is_some()));
+  let mut mesh = MeshData::new(Vec::new(), Vec::new());
+  if !with_uvs && !with_normals {
+    mesh.verts = positions;
+    mesh.colors = colors;
+    mesh.faces = triangles.iter().map(|t| Vector3i::new(t[0].0 as i32, t[1].0 as i32, t[2].0 as i32)).collect();
+    return Ok(mesh);
+  }
+  let mut vertices: HashMap<Corner, i32> = HashMap::new();
+  for t in &triangles {
+    let mut face = [0; 3];
+    for (k, corner) in t.iter().enumerate() {
+      face[k] = *vertices.entry(*corner).or_insert_with(|| {
+        mesh.verts.push(positions[corner.0]);
+        if !colors.is_empty() {
+          mesh.colors.push(colors[corner.0]);
+        }
+        if with_uvs {
+          mesh.uvs.push(uvs[corner.1.unwrap()]);
+        }
+        if with_normals {
+          mesh.normals.push(normals[corner.2.unwrap()]);
+        }
+        mesh.verts.len() as i32 - 1
+      });
+    }
+    mesh.faces.push(Vector3i::new(face[0], face[1], face[2]));
+  }
+  return Ok(mesh);
+}
+
+//OBJ indices start at 1, negative ones count back from the newest element.
+fn obj_index(text: &str, count: usize, kind: &str) -> Result<usize, String> {
+  let index: i64 = text.parse().map_err(|_| format!("expected a {} index but found '{}'", kind, text))?;
+  let resolved = if index < 0 {count as i64 + index} else {index - 1};
+  if index == 0 || resolved < 0 || resolved >= count as i64 {
+    return Err(format!("{} index {} doesn't exist (there are {} so far)", kind, index, count));
+  }
+  return Ok(resolved as usize);
 }
- 
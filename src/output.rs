@@ -0,0 +1,298 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Settings;
+
+//Import the tone mapping from tonemap.rs
+use tonemap::srgb_encode;
+
+//Import our random number generator from random.rs
+use random::Rng;
+
+//Import the render passes from passes.rs
+use passes::Aov;
+use passes::Encoding;
+
+//Import the OpenEXR writer from exr.rs
+use exr::ExrChannel;
+use exr::write_exr;
+
+//Import all of the standard libraries we need.
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::path::Path;
+use std::time::{Instant};
+
+//Expands a printf style frame number ("frame_%04d.png") in an output pattern.
+//Patterns without a placeholder get the frame number added before the extension when animating.
+pub fn frame_filename(pattern: &str, frame: i32, animated: bool) -> String{
+  if let Some(start) = pattern.find('%') {
+    if let Some(length) = pattern[start..].find('d') {
+      let spec = &pattern[start + 1..start + length];
+      if spec.chars().all(|c| c.is_ascii_digit()) {
+        let width: usize = spec.parse().unwrap_or(0);
+        let number = if spec.starts_with('0') {format!("{:0width$}", frame, width = width)} else {format!("{:width$}", frame, width = width)};
+        return format!("{}{}{}", &pattern[..start], number, &pattern[start + length + 1..]);
+      }
+    }
+  }
+  if animated {
+    return match pattern.rfind('.') {
+      Some(dot) => format!("{}_{:04}{}", &pattern[..dot], frame, &pattern[dot..]),
+      None => format!("{}_{:04}", pattern, frame),
+    };
+  }
+  return pattern.to_string();
+}
+
+//Writes the framebuffer and its render passes in the format matching the file extension (PPM if it isn't recognised).
+//EXR files hold every pass as a layer, other formats get one extra file per pass ("out_depth.png").
+pub fn write_framebuffer(filename: &str, framebuffer: &Vec<Vector3>, aovs: &Vec<Aov>, settings: &Settings) -> io::Result<()>{
+  let start_time = Instant::now();
+  let extension = Path::new(filename).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+  if extension == "exr" {
+    let mut channels = vec![
+      ExrChannel::new("R", framebuffer.iter().map(|p| p.x).collect()),
+      ExrChannel::new("G", framebuffer.iter().map(|p| p.y).collect()),
+      ExrChannel::new("B", framebuffer.iter().map(|p| p.z).collect()),
+    ];
+    for pass in &settings.passes {
+      for (i, channel) in pass.channels().iter().enumerate() {
+        let data = aovs.iter().map(|a| {
+          let v = pass.value(a);
+          [v.x, v.y, v.z][i]
+        }).collect();
+        let name = format!("{}.{}", pass.name(), channel);
+        channels.push(if pass.is_id() {ExrChannel::integer(&name, data)} else {ExrChannel::new(&name, data)});
+      }
+    }
+    write_exr(filename, &channels, settings.fheight, settings.fwidth, settings.exr_compression, settings.exr_half)?;
+  }
+  else {
+    write_image(filename, &extension, framebuffer, settings, Encoding::Radiance)?;
+    for pass in &settings.passes {
+      let pass_filename = match filename.rfind('.') {
+        Some(dot) => format!("{}_{}{}", &filename[..dot], pass.name(), &filename[dot..]),
+        None => format!("{}_{}", filename, pass.name()),
+      };
+      //Float formats keep the raw values, single channel passes are repeated across RGB.
+      let image: Vec<Vector3> = if extension == "pfm" || extension == "hdr" {
+        aovs.iter().map(|a| {
+          let v = pass.value(a);
+          if pass.channels().len() == 1 {Vector3::new(v.x, v.x, v.x)} else {v}
+        }).collect()
+      } else {
+        pass.image(aovs)
+      };
+      write_image(&pass_filename, &extension, &image, settings, pass.encoding())?;
+    }
+  }
+  let duration = start_time.elapsed();
+  println!("Wrote {} in {} seconds.", filename, duration.as_secs_f64());
+  Ok(())
+}
+
+//Writes a single image, PFM and HDR files get the raw values, everything else is encoded to 8 bits first.
+fn write_image(filename: &str, extension: &str, image: &Vec<Vector3>, settings: &Settings, encoding: Encoding) -> io::Result<()>{
+  let fheight = settings.fheight;
+  let fwidth = settings.fwidth;
+  match extension {
+    "pfm" => framebuffer_to_pfm(filename, image, fheight, fwidth),
+    "hdr" => framebuffer_to_hdr(filename, image, fheight, fwidth),
+    "png" => framebuffer_to_png(filename, &framebuffer_to_rgb(image, settings, encoding), fheight, fwidth),
+    _ => framebuffer_to_ppm(filename, &framebuffer_to_rgb(image, settings, encoding), fheight, fwidth),
+  }
+}
+
+//Expose, tone map and sRGB encode the framebuffer, then scale between 0 to 255 and convert to u8.
+fn framebuffer_to_rgb(framebuffer: &Vec<Vector3>, settings: &Settings, encoding: Encoding) -> Vec<u8>{
+  let mut pixels: Vec<u8> = Vec::with_capacity(framebuffer.len()*3);
+  let exposure = (2.0 as f32).powf(settings.exposure);
+  for (i, pixel) in framebuffer.iter().enumerate() {
+    let c = match encoding {
+      Encoding::Radiance => settings.tone_map.apply(*pixel * exposure),
+      _ => *pixel,
+    };
+    let c = match encoding {
+      Encoding::Data => c * 255.0,
+      _ => Vector3::new(srgb_encode(c.x), srgb_encode(c.y), srgb_encode(c.z)) * 255.0,
+    };
+    //Triangular noise of up to one step either way breaks up banding in smooth gradients.
+    let mut offset = Vector3::new(0.5, 0.5, 0.5);
+    if settings.dither && encoding != Encoding::Data {
+      let mut rng = Rng::new(i as u32);
+      offset = offset + Vector3::new(rng.next_f32() - rng.next_f32(), rng.next_f32() - rng.next_f32(), rng.next_f32() - rng.next_f32());
+    }
+    let c = c + offset;
+    pixels.extend_from_slice(&Vector3::tou8(Vector3::new(c.x.max(0.0).min(255.0), c.y.max(0.0).min(255.0), c.z.max(0.0).min(255.0))));
+  }
+  return pixels;
+}
+
+//Write the pixels to a ppm file.
+fn framebuffer_to_ppm(filename: &str, pixels: &Vec<u8>, fheight: usize, fwidth: usize) -> io::Result<()>{
+  let mut file = BufWriter::new(File::create(filename)?);
+  //Write the header for the PPM file (P6 format, width, height, max color value).
+  writeln!(file, "P6")?;
+  writeln!(file, "{} {}", fwidth, fheight)?;
+  writeln!(file, "255")?;
+  file.write_all(pixels)?;
+  Ok(())
+}
+
+//Write the pixels to a png file, the image data is stored without compression so we don't need zlib.
+fn framebuffer_to_png(filename: &str, pixels: &Vec<u8>, fheight: usize, fwidth: usize) -> io::Result<()>{
+  let mut file = BufWriter::new(File::create(filename)?);
+  file.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'])?;
+
+  //Header: width, height, 8 bits per channel, truecolor, default compression/filter/interlace.
+  let mut header: Vec<u8> = Vec::new();
+  header.extend_from_slice(&(fwidth as u32).to_be_bytes());
+  header.extend_from_slice(&(fheight as u32).to_be_bytes());
+  header.extend_from_slice(&[8, 2, 0, 0, 0]);
+  png_chunk(&mut file, b"IHDR", &header)?;
+
+  //Every scanline starts with a filter byte (0 = none).
+  let mut raw: Vec<u8> = Vec::with_capacity((fwidth*3 + 1)*fheight);
+  for y in 0..fheight {
+    raw.push(0);
+    raw.extend_from_slice(&pixels[y*fwidth*3..(y + 1)*fwidth*3]);
+  }
+
+  //Wrap the scanlines in a zlib stream made of stored deflate blocks.
+  let mut data: Vec<u8> = vec![0x78, 0x01];
+  let blocks: Vec<&[u8]> = raw.chunks(65535).collect();
+  for (i, block) in blocks.iter().enumerate() {
+    data.push(if i + 1 == blocks.len() {1} else {0});
+    data.extend_from_slice(&(block.len() as u16).to_le_bytes());
+    data.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+    data.extend_from_slice(block);
+  }
+  if blocks.is_empty() {
+    data.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+  }
+  data.extend_from_slice(&adler32(&raw).to_be_bytes());
+  png_chunk(&mut file, b"IDAT", &data)?;
+  png_chunk(&mut file, b"IEND", &[])?;
+  Ok(())
+}
+
+//Write the framebuffer to a little endian pfm file (32-bit floats, rows stored bottom to top).
+fn framebuffer_to_pfm(filename: &str, framebuffer: &Vec<Vector3>, fheight: usize, fwidth: usize) -> io::Result<()>{
+  let mut file = BufWriter::new(File::create(filename)?);
+  writeln!(file, "PF")?;
+  writeln!(file, "{} {}", fwidth, fheight)?;
+  //A negative scale marks the data as little endian.
+  writeln!(file, "-1.0")?;
+  for y in (0..fheight).rev() {
+    for pixel in &framebuffer[y*fwidth..(y + 1)*fwidth] {
+      file.write_all(&pixel.x.to_le_bytes())?;
+      file.write_all(&pixel.y.to_le_bytes())?;
+      file.write_all(&pixel.z.to_le_bytes())?;
+    }
+  }
+  Ok(())
+}
+
+//Write the framebuffer to a Radiance hdr file, RGBE pixels with run length encoded scanlines.
+fn framebuffer_to_hdr(filename: &str, framebuffer: &Vec<Vector3>, fheight: usize, fwidth: usize) -> io::Result<()>{
+  let mut file = BufWriter::new(File::create(filename)?);
+  writeln!(file, "#?RADIANCE")?;
+  writeln!(file, "FORMAT=32-bit_rle_rgbe")?;
+  writeln!(file)?;
+  writeln!(file, "-Y {} +X {}", fheight, fwidth)?;
+  for y in 0..fheight {
+    let scanline: Vec<[u8; 4]> = framebuffer[y*fwidth..(y + 1)*fwidth].iter().map(|p| rgbe(*p)).collect();
+    //The run length encoding only works for widths between 8 and 32767, otherwise write flat pixels.
+    if fwidth < 8 || fwidth > 0x7fff {
+      for pixel in &scanline {
+        file.write_all(pixel)?;
+      }
+      continue;
+    }
+    file.write_all(&[2, 2, (fwidth >> 8) as u8, (fwidth & 0xff) as u8])?;
+    //Each channel is encoded separately.
+    for channel in 0..4 {
+      let values: Vec<u8> = scanline.iter().map(|p| p[channel]).collect();
+      file.write_all(&rle(&values))?;
+    }
+  }
+  Ok(())
+}
+
+//Shared exponent encoding, the largest channel decides the exponent.
+fn rgbe(pixel: Vector3) -> [u8; 4]{
+  let v = f32::max(pixel.x, f32::max(pixel.y, pixel.z));
+  if !(v > 1e-32) {
+    return [0, 0, 0, 0];
+  }
+  //v = mantissa * 2^exponent with the mantissa in [0.5, 1).
+  let mut exponent = v.log2().floor() as i32 + 1;
+  if v/(2.0 as f32).powi(exponent) >= 1.0 {
+    exponent += 1;
+  }
+  let exponent = exponent.max(-128).min(127);
+  let scale = 256.0/(2.0 as f32).powi(exponent);
+  let channel = |c: f32| (c.max(0.0)*scale).min(255.0) as u8;
+  return [channel(pixel.x), channel(pixel.y), channel(pixel.z), (exponent + 128) as u8];
+}
+
+//Radiance run length encoding: runs are a count above 128 followed by a value, the rest are literal spans.
+fn rle(values: &Vec<u8>) -> Vec<u8>{
+  let mut encoded: Vec<u8> = Vec::new();
+  let mut i = 0;
+  while i < values.len() {
+    //Measure the run starting here.
+    let mut run = 1;
+    while i + run < values.len() && run < 127 && values[i + run] == values[i] {
+      run += 1;
+    }
+    if run >= 4 {
+      encoded.push(128 + run as u8);
+      encoded.push(values[i]);
+      i += run;
+      continue;
+    }
+    //Collect literals until the next run worth encoding.
+    let start = i;
+    while i < values.len() && i - start < 128 {
+      if i + 3 < values.len() && values[i] == values[i + 1] && values[i] == values[i + 2] && values[i] == values[i + 3] {
+        break;
+      }
+      i += 1;
+    }
+    encoded.push((i - start) as u8);
+    encoded.extend_from_slice(&values[start..i]);
+  }
+  return encoded;
+}
+
+fn png_chunk<W: Write>(file: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()>{
+  file.write_all(&(data.len() as u32).to_be_bytes())?;
+  file.write_all(kind)?;
+  file.write_all(data)?;
+  let crc = crc32(&[&kind[..], data].concat());
+  file.write_all(&crc.to_be_bytes())?;
+  Ok(())
+}
+
+fn crc32(data: &[u8]) -> u32{
+  let mut crc: u32 = 0xffffffff;
+  for byte in data {
+    crc ^= *byte as u32;
+    for _ in 0..8 {
+      let mask = (!(crc & 1)).wrapping_add(1);
+      crc = (crc >> 1) ^ (0xedb88320 & mask);
+    }
+  }
+  return !crc;
+}
+
+fn adler32(data: &[u8]) -> u32{
+  let mut a: u32 = 1;
+  let mut b: u32 = 0;
+  for byte in data {
+    a = (a + *byte as u32) % 65521;
+    b = (b + a) % 65521;
+  }
+  return (b << 16) | a;
+}
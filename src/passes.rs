@@ -0,0 +1,279 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Vector2;
+
+//Import the hash from random.rs
+use random::hash;
+
+//Auxiliary outputs (AOVs) that can be written next to the beauty image.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Pass{
+  Depth,
+  Normal,
+  Uv,
+  Albedo,
+  Direct,
+  Indirect,
+  Shadow,
+  ObjectId,
+  MaterialId,
+  Samples,
+}
+
+//How a pass is turned into an 8 bit image: radiance gets the full tone mapping, colors only the sRGB curve, data is written as is.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Encoding{
+  Radiance,
+  Color,
+  Data,
+}
+
+impl Pass{
+
+  pub fn from_name(name: &str) -> Option<Pass> {
+    match name {
+      "depth" => Some(Pass::Depth),
+      "normal" => Some(Pass::Normal),
+      "uv" => Some(Pass::Uv),
+      "albedo" => Some(Pass::Albedo),
+      "direct" => Some(Pass::Direct),
+      "indirect" => Some(Pass::Indirect),
+      "shadow" => Some(Pass::Shadow),
+      "object" => Some(Pass::ObjectId),
+      "material" => Some(Pass::MaterialId),
+      "samples" => Some(Pass::Samples),
+      _ => None,
+    }
+  }
+
+  //Used for the EXR layer and the file name suffix of the pass.
+  pub fn name(&self) -> &'static str {
+    match self {
+      Pass::Depth => "depth",
+      Pass::Normal => "normal",
+      Pass::Uv => "uv",
+      Pass::Albedo => "albedo",
+      Pass::Direct => "direct",
+      Pass::Indirect => "indirect",
+      Pass::Shadow => "shadow",
+      Pass::ObjectId => "object_id",
+      Pass::MaterialId => "material_id",
+      Pass::Samples => "samples",
+    }
+  }
+
+  //Channel names of the pass inside an EXR layer.
+  pub fn channels(&self) -> &'static [&'static str] {
+    match self {
+      Pass::Depth => &["Z"],
+      Pass::Normal => &["X", "Y", "Z"],
+      Pass::Uv => &["U", "V"],
+      Pass::Albedo | Pass::Direct | Pass::Indirect => &["R", "G", "B"],
+      Pass::Shadow | Pass::Samples => &["Y"],
+      Pass::ObjectId | Pass::MaterialId => &["ID"],
+    }
+  }
+
+  //ID passes hold whole numbers that have to survive exactly.
+  pub fn is_id(&self) -> bool {
+    *self == Pass::ObjectId || *self == Pass::MaterialId
+  }
+
+  pub fn encoding(&self) -> Encoding {
+    match self {
+      Pass::Direct | Pass::Indirect => Encoding::Radiance,
+      Pass::Albedo => Encoding::Color,
+      _ => Encoding::Data,
+    }
+  }
+
+  //The raw values of the pass for one pixel, single channel passes only use x.
+  pub fn value(&self, aov: &Aov) -> Vector3 {
+    match self {
+      Pass::Depth => Vector3::new(aov.depth, 0.0, 0.0),
+      Pass::Normal => aov.normal,
+      Pass::Uv => Vector3::new(aov.uv.x, aov.uv.y, 0.0),
+      Pass::Albedo => aov.albedo,
+      Pass::Direct => aov.direct,
+      Pass::Indirect => aov.indirect,
+      Pass::Shadow => Vector3::new(aov.shadow, 0.0, 0.0),
+      Pass::ObjectId => Vector3::new(aov.object_id as f32, 0.0, 0.0),
+      Pass::MaterialId => Vector3::new(aov.material_id as f32, 0.0, 0.0),
+      Pass::Samples => Vector3::new(aov.samples as f32, 0.0, 0.0),
+    }
+  }
+
+  //The pass as an image that can be viewed in a regular image file.
+  pub fn image(&self, aovs: &Vec<Aov>) -> Vec<Vector3> {
+    match self {
+      Pass::Depth => {
+        //Normalize by the farthest surface, the background stays white.
+        let far = aovs.iter().map(|a| a.depth).filter(|d| *d < BACKGROUND_DEPTH).fold(0.0, f32::max);
+        aovs.iter().map(|a| {
+          let d = if far > 0.0 {f32::min(a.depth/far, 1.0)} else {1.0};
+          Vector3::new(d, d, d)
+        }).collect()
+      },
+      Pass::Normal => aovs.iter().map(|a| a.normal*0.5 + Vector3::new(0.5, 0.5, 0.5)).collect(),
+      //UVs outside 0 to 1 (like on planes) repeat, so the tiling is visible.
+      Pass::Uv => aovs.iter().map(|a| Vector3::new(a.uv.x - a.uv.x.floor(), a.uv.y - a.uv.y.floor(), 0.0)).collect(),
+      Pass::Shadow => aovs.iter().map(|a| Vector3::new(a.shadow, a.shadow, a.shadow)).collect(),
+      Pass::ObjectId => aovs.iter().map(|a| id_color(a.object_id)).collect(),
+      Pass::MaterialId => aovs.iter().map(|a| id_color(a.material_id)).collect(),
+      Pass::Samples => {
+        //Heatmap from blue (fewest samples) to red (most samples).
+        let most = aovs.iter().map(|a| a.samples).max().unwrap_or(0);
+        let least = aovs.iter().map(|a| a.samples).min().unwrap_or(0);
+        aovs.iter().map(|a| heat_color(if most > least {(a.samples - least) as f32/(most - least) as f32} else {0.0})).collect()
+      },
+      _ => aovs.iter().map(|a| self.value(a)).collect(),
+    }
+  }
+
+}
+
+//Depth given to pixels that don't hit anything.
+pub const BACKGROUND_DEPTH: f32 = 1e10;
+
+//Everything we know about the first surface a camera ray hit, IDs start at 1 and 0 is the background.
+#[derive(Debug, Copy, Clone)]
+pub struct Aov{
+  pub depth: f32,
+  pub normal: Vector3,
+  pub uv: Vector2,
+  pub albedo: Vector3,
+  pub direct: Vector3,
+  pub indirect: Vector3,
+  pub shadow: f32,
+  pub object_id: u32,
+  pub material_id: u32,
+  pub samples: u32,
+}
+
+impl Aov{
+
+  pub fn background(background_color: Vector3) -> Self {
+    let zero = Vector3::new(0.0, 0.0, 0.0);
+    Aov {depth: BACKGROUND_DEPTH, normal: zero, uv: Vector2::new(0.0, 0.0), albedo: background_color, direct: background_color, indirect: zero, shadow: 0.0, object_id: 0, material_id: 0, samples: 0}
+  }
+
+}
+
+//How many different IDs a pixel keeps track of, a pixel rarely sees more objects than this.
+const VOTES: usize = 4;
+
+//Collects the samples of one pixel, the continuous passes are averaged and IDs go to whatever covers the most samples.
+#[derive(Debug, Copy, Clone)]
+pub struct AovAccumulator{
+  pub sum: Aov,
+  pub count: f32,
+  pub hits: f32,
+  pub objects: [(u32, u32); VOTES],
+  pub materials: [(u32, u32); VOTES],
+}
+
+impl AovAccumulator{
+
+  pub fn new() -> Self {
+    let zero = Vector3::new(0.0, 0.0, 0.0);
+    AovAccumulator {sum: Aov {depth: 0.0, normal: zero, uv: Vector2::new(0.0, 0.0), albedo: zero, direct: zero, indirect: zero, shadow: 0.0, object_id: 0, material_id: 0, samples: 0}, count: 0.0, hits: 0.0, objects: [(0, 0); VOTES], materials: [(0, 0); VOTES]}
+  }
+
+  pub fn add(&mut self, aov: &Aov) {
+    //Only surfaces count towards the depth, so edges don't get pulled out to the background.
+    if aov.object_id != 0 {
+      self.sum.depth += aov.depth;
+      self.hits += 1.0;
+    }
+    self.sum.normal = self.sum.normal + aov.normal;
+    self.sum.uv = Vector2::new(self.sum.uv.x + aov.uv.x, self.sum.uv.y + aov.uv.y);
+    self.sum.albedo = self.sum.albedo + aov.albedo;
+    self.sum.direct = self.sum.direct + aov.direct;
+    self.sum.indirect = self.sum.indirect + aov.indirect;
+    self.sum.shadow += aov.shadow;
+    self.count += 1.0;
+    vote(&mut self.objects, aov.object_id, 1);
+    vote(&mut self.materials, aov.material_id, 1);
+  }
+
+  //Adds the samples collected somewhere else (another render pass) for the same pixel.
+  pub fn merge(&mut self, other: &AovAccumulator) {
+    self.sum.depth += other.sum.depth;
+    self.hits += other.hits;
+    self.sum.normal = self.sum.normal + other.sum.normal;
+    self.sum.uv = Vector2::new(self.sum.uv.x + other.sum.uv.x, self.sum.uv.y + other.sum.uv.y);
+    self.sum.albedo = self.sum.albedo + other.sum.albedo;
+    self.sum.direct = self.sum.direct + other.sum.direct;
+    self.sum.indirect = self.sum.indirect + other.sum.indirect;
+    self.sum.shadow += other.sum.shadow;
+    self.count += other.count;
+    for v in other.objects.iter().filter(|v| v.1 > 0) {
+      vote(&mut self.objects, v.0, v.1);
+    }
+    for v in other.materials.iter().filter(|v| v.1 > 0) {
+      vote(&mut self.materials, v.0, v.1);
+    }
+  }
+
+  pub fn finish(&self, samples: u32) -> Aov {
+    let scale = 1.0/f32::max(self.count, 1.0);
+    Aov{
+      depth: if self.hits > 0.0 {self.sum.depth/self.hits} else {BACKGROUND_DEPTH},
+      normal: self.sum.normal.normalize(),
+      uv: Vector2::new(self.sum.uv.x*scale, self.sum.uv.y*scale),
+      albedo: self.sum.albedo*scale,
+      direct: self.sum.direct*scale,
+      indirect: self.sum.indirect*scale,
+      shadow: self.sum.shadow*scale,
+      object_id: winner(&self.objects),
+      material_id: winner(&self.materials),
+      samples: samples,
+    }
+  }
+
+}
+
+//Counts a vote for an ID, when every slot is taken the weakest one is replaced.
+fn vote(votes: &mut [(u32, u32); VOTES], id: u32, count: u32) {
+  if let Some(v) = votes.iter_mut().find(|v| v.1 > 0 && v.0 == id) {
+    v.1 += count;
+    return;
+  }
+  let mut weakest = 0;
+  for i in 1..VOTES {
+    if votes[i].1 < votes[weakest].1 {
+      weakest = i;
+    }
+  }
+  if votes[weakest].1 < count {
+    votes[weakest] = (id, count);
+  }
+}
+
+fn winner(votes: &[(u32, u32); VOTES]) -> u32 {
+  let mut best = (0, 0);
+  for v in votes {
+    if v.1 > best.1 {
+      best = *v;
+    }
+  }
+  return best.0;
+}
+
+//Blue through green to red.
+fn heat_color(t: f32) -> Vector3 {
+  let t = t.max(0.0).min(1.0);
+  if t < 0.5 {
+    return Vector3::new(0.0, t*2.0, 1.0 - t*2.0);
+  }
+  return Vector3::new((t - 0.5)*2.0, 1.0 - (t - 0.5)*2.0, 0.0);
+}
+
+//Gives every ID a stable, easy to tell apart color (the background stays black).
+fn id_color(id: u32) -> Vector3 {
+  if id == 0 {
+    return Vector3::new(0.0, 0.0, 0.0);
+  }
+  let h = hash(id);
+  return Vector3::new((h & 0xff) as f32/255.0, ((h >> 8) & 0xff) as f32/255.0, ((h >> 16) & 0xff) as f32/255.0);
+}
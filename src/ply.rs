@@ -0,0 +1,272 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Vector3i;
+use definitions::Vector2;
+
+//Import the mesh data from model.rs
+use model::MeshData;
+
+use std::fs;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format{
+  Ascii,
+  BinaryLittleEndian,
+  BinaryBigEndian,
+}
+
+//The number types a property can have, with both the old and the sized names.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scalar{
+  Int8,
+  Uint8,
+  Int16,
+  Uint16,
+  Int32,
+  Uint32,
+  Float32,
+  Float64,
+}
+
+impl Scalar{
+
+  fn from_name(name: &str) -> Result<Scalar, String> {
+    match name {
+      "char" | "int8" => Ok(Scalar::Int8),
+      "uchar" | "uint8" => Ok(Scalar::Uint8),
+      "short" | "int16" => Ok(Scalar::Int16),
+      "ushort" | "uint16" => Ok(Scalar::Uint16),
+      "int" | "int32" => Ok(Scalar::Int32),
+      "uint" | "uint32" => Ok(Scalar::Uint32),
+      "float" | "float32" => Ok(Scalar::Float32),
+      "double" | "float64" => Ok(Scalar::Float64),
+      _ => Err(format!("unknown property type '{}'", name)),
+    }
+  }
+
+  fn size(&self) -> usize {
+    match self {
+      Scalar::Int8 | Scalar::Uint8 => 1,
+      Scalar::Int16 | Scalar::Uint16 => 2,
+      Scalar::Int32 | Scalar::Uint32 | Scalar::Float32 => 4,
+      Scalar::Float64 => 8,
+    }
+  }
+
+  //Colors stored as integers go from 0 to the largest value of their type.
+  fn color_scale(&self) -> f64 {
+    match self {
+      Scalar::Uint8 => 255.0,
+      Scalar::Uint16 => 65535.0,
+      _ => 1.0,
+    }
+  }
+
+}
+
+//A property is either one number or a list with its length in front.
+#[derive(Debug, Clone)]
+struct Property{
+  name: String,
+  kind: Scalar,
+  count: Option<Scalar>,
+}
+
+#[derive(Debug, Clone)]
+struct Element{
+  name: String,
+  count: usize,
+  properties: Vec<Property>,
+}
+
+//Where the numbers after the header come from.
+enum Body<'a>{
+  Ascii(std::str::SplitAsciiWhitespace<'a>),
+  Binary{data: &'a [u8], position: usize, big_endian: bool},
+}
+
+impl<'a> Body<'a>{
+
+  fn read(&mut self, kind: Scalar) -> Result<f64, String> {
+    match self {
+      Body::Ascii(tokens) => {
+        let token = tokens.next().ok_or("the file ends too early")?;
+        token.parse().map_err(|_| format!("expected a number but found '{}'", token))
+      },
+      Body::Binary{data, position, big_endian} => {
+        let size = kind.size();
+        if *position + size > data.len() {
+          return Err("the file ends too early".to_string());
+        }
+        let mut bytes = [0u8; 8];
+        bytes[..size].copy_from_slice(&data[*position..*position + size]);
+        *position += size;
+        if *big_endian {
+          bytes[..size].reverse();
+        }
+        Ok(match kind {
+          Scalar::Int8 => bytes[0] as i8 as f64,
+          Scalar::Uint8 => bytes[0] as f64,
+          Scalar::Int16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+          Scalar::Uint16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+          Scalar::Int32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+          Scalar::Uint32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+          Scalar::Float32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+          Scalar::Float64 => f64::from_le_bytes(bytes),
+        })
+      },
+    }
+  }
+
+}
+
+//Reads a Stanford PLY file (ASCII or binary of either byte order).
+//Vertices can have normals (nx ny nz), colors (red green blue) and UVs (u v, s t or texture_u texture_v), faces are split into triangles.
+pub fn load_ply(filename: &str) -> Result<MeshData, String> {
+  let data = fs::read(filename).map_err(|e| e.to_string())?;
+  let (format, elements, body_start) = read_header(&data)?;
+  let mut body = match format {
+    Format::Ascii => Body::Ascii(std::str::from_utf8(&data[body_start..]).map_err(|_| "the ASCII data isn't valid text")?.split_ascii_whitespace()),
+    _ => Body::Binary{data: &data[body_start..], position: 0, big_endian: format == Format::BinaryBigEndian},
+  };
+
+  let mut mesh = MeshData::new(Vec::new(), Vec::new());
+  for element in &elements {
+    match element.name.as_str() {
+      "vertex" => read_vertices(element, &mut body, &mut mesh)?,
+      "face" => read_faces(element, &mut body, &mut mesh)?,
+      //Anything else (edges, materials...) is read and thrown away, binary data has to be walked through anyway.
+      _ => for _ in 0..element.count {
+        read_row(element, &mut body)?;
+      },
+    }
+  }
+
+  let vertex_count = mesh.verts.len() as i32;
+  if mesh.faces.iter().any(|f| f.x < 0 || f.y < 0 || f.z < 0 || f.x >= vertex_count || f.y >= vertex_count || f.z >= vertex_count) {
+    return Err("a face uses a vertex that doesn't exist".to_string());
+  }
+  return Ok(mesh);
+}
+
+fn read_header(data: &[u8]) -> Result<(Format, Vec<Element>, usize), String> {
+  let mut format: Option<Format> = None;
+  let mut elements: Vec<Element> = Vec::new();
+  let mut position = 0;
+  let mut first = true;
+  loop {
+    let end = match data[position..].iter().position(|&b| b == b'\n') {
+      Some(end) => position + end,
+      None => return Err("the header has no end_header".to_string()),
+    };
+    let line = std::str::from_utf8(&data[position..end]).map_err(|_| "the header isn't valid text")?.trim();
+    position = end + 1;
+    if first {
+      if line != "ply" {
+        return Err("this isn't a PLY file".to_string());
+      }
+      first = false;
+      continue;
+    }
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    match parts.first().map(|p| *p) {
+      Some("format") => format = Some(match parts.get(1).map(|p| *p) {
+        Some("ascii") => Format::Ascii,
+        Some("binary_little_endian") => Format::BinaryLittleEndian,
+        Some("binary_big_endian") => Format::BinaryBigEndian,
+        _ => return Err(format!("unknown format '{}'", line)),
+      }),
+      Some("element") if parts.len() == 3 => elements.push(Element {
+        name: parts[1].to_string(),
+        count: parts[2].parse().map_err(|_| format!("bad element count in '{}'", line))?,
+        properties: Vec::new(),
+      }),
+      Some("property") => {
+        let element = elements.last_mut().ok_or("a property comes before any element")?;
+        let property = match parts.as_slice() {
+          [_, "list", count, kind, name] => Property {name: name.to_string(), kind: Scalar::from_name(kind)?, count: Some(Scalar::from_name(count)?)},
+          [_, kind, name] => Property {name: name.to_string(), kind: Scalar::from_name(kind)?, count: None},
+          _ => return Err(format!("can't read '{}'", line)),
+        };
+        element.properties.push(property);
+      },
+      Some("end_header") => break,
+      Some("comment") | Some("obj_info") | None => (),
+      _ => return Err(format!("can't read '{}'", line)),
+    }
+  }
+  match format {
+    Some(format) => Ok((format, elements, position)),
+    None => Err("the header has no format".to_string()),
+  }
+}
+
+//Reads one row of an element, a list gives all of its numbers.
+fn read_row(element: &Element, body: &mut Body) -> Result<Vec<Vec<f64>>, String> {
+  let mut row = Vec::with_capacity(element.properties.len());
+  for property in &element.properties {
+    match property.count {
+      Some(count) => {
+        //The length comes from the file, so the list grows as it's read instead of trusting it up front.
+        let length = body.read(count)? as usize;
+        let mut list = Vec::new();
+        for _ in 0..length {
+          list.push(body.read(property.kind)?);
+        }
+        row.push(list);
+      },
+      None => row.push(vec![body.read(property.kind)?]),
+    }
+  }
+  return Ok(row);
+}
+
+fn read_vertices(element: &Element, body: &mut Body, mesh: &mut MeshData) -> Result<(), String> {
+  let find = |names: &[&str]| element.properties.iter().position(|p| names.contains(&p.name.as_str()) && p.count.is_none());
+  let position = match (find(&["x"]), find(&["y"]), find(&["z"])) {
+    (Some(x), Some(y), Some(z)) => [x, y, z],
+    _ => return Err("the vertices have no x, y and z".to_string()),
+  };
+  let normal = match (find(&["nx"]), find(&["ny"]), find(&["nz"])) {
+    (Some(x), Some(y), Some(z)) => Some([x, y, z]),
+    _ => None,
+  };
+  let color = match (find(&["red", "r"]), find(&["green", "g"]), find(&["blue", "b"])) {
+    (Some(r), Some(g), Some(b)) => Some([r, g, b]),
+    _ => None,
+  };
+  let uv = match (find(&["u", "s", "texture_u", "texture_s"]), find(&["v", "t", "texture_v", "texture_t"])) {
+    (Some(u), Some(v)) => Some([u, v]),
+    _ => None,
+  };
+
+  for _ in 0..element.count {
+    let row = read_row(element, body)?;
+    let value = |i: usize| row[i][0] as f32;
+    mesh.verts.push(Vector3::new(value(position[0]), value(position[1]), value(position[2])));
+    if let Some(n) = normal {
+      mesh.normals.push(Vector3::new(value(n[0]), value(n[1]), value(n[2])).normalize());
+    }
+    if let Some(c) = color {
+      let channel = |i: usize| (row[i][0]/element.properties[i].kind.color_scale()) as f32;
+      mesh.colors.push(Vector3::new(channel(c[0]), channel(c[1]), channel(c[2])));
+    }
+    if let Some(t) = uv {
+      mesh.uvs.push(Vector2::new(value(t[0]), value(t[1])));
+    }
+  }
+  return Ok(());
+}
+
+fn read_faces(element: &Element, body: &mut Body, mesh: &mut MeshData) -> Result<(), String> {
+  let indices = element.properties.iter().position(|p| (p.name == "vertex_indices" || p.name == "vertex_index") && p.count.is_some())
+    .ok_or("the faces have no vertex_indices list")?;
+  for _ in 0..element.count {
+    let row = read_row(element, body)?;
+    let face = &row[indices];
+    for i in 2..face.len() {
+      mesh.faces.push(Vector3i::new(face[0] as i32, face[i - 1] as i32, face[i] as i32));
+    }
+  }
+  return Ok(());
+}
@@ -0,0 +1,322 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Matrix3;
+use definitions::Hit;
+use definitions::Material;
+
+//Import the integer hash from random.rs
+use random::hash;
+
+//Import the directions of the UVs on a surface from texture.rs
+use texture::tangent_basis;
+
+//Where a pattern is looked up: in the object's own space (it moves and turns with the object), in the world, or on the UVs (u, v, 0).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Space{
+  Object,
+  World,
+  Uv,
+}
+
+//A procedural texture built from generators and combinators, it gives a color for any point.
+//Generators that make a single value (like the noises) give it as a grey from 0 to 1.
+#[derive(Debug, Clone)]
+pub enum PatternNode{
+  Color(Vector3),
+  Checker(f32, Box<PatternNode>, Box<PatternNode>),
+  Gradient(usize, f32, f32, Box<PatternNode>, Box<PatternNode>),
+  Perlin(f32),
+  Simplex(f32),
+  Fbm(f32, u32),
+  Worley(f32),
+  Marble(f32, f32, Box<PatternNode>, Box<PatternNode>),
+  Wood(f32, f32, Box<PatternNode>, Box<PatternNode>),
+  Mix(Box<PatternNode>, Box<PatternNode>, Box<PatternNode>),
+  Multiply(Box<PatternNode>, Box<PatternNode>),
+  Translate(Vector3, Box<PatternNode>),
+  Scale(f32, Box<PatternNode>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern{
+  pub root: PatternNode,
+  pub space: Space,
+}
+
+//How an object sits in the world, to turn hit points into its own space.
+#[derive(Debug, Copy, Clone)]
+pub struct Frame{
+  pub origin: Vector3,
+  pub rotation: Matrix3,
+  pub scale: f32,
+}
+
+impl Frame{
+
+  pub fn new(origin: Vector3, rotation: Matrix3, scale: f32) -> Self {
+    Frame {origin, rotation, scale}
+  }
+
+  //Objects that don't have a place of their own (CSG and SDF objects) use the world.
+  pub fn world() -> Self {
+    Frame::new(Vector3::new(0.0, 0.0, 0.0), Matrix3::identity(), 1.0)
+  }
+
+  pub fn to_local(&self, point: Vector3) -> Vector3 {
+    self.rotation.transpose().apply(point - self.origin)*(1.0/self.scale)
+  }
+
+  //Turns a gradient from the object's space back into the world.
+  pub fn gradient_to_world(&self, gradient: Vector3) -> Vector3 {
+    self.rotation.apply(gradient)*(1.0/self.scale)
+  }
+
+}
+
+impl PatternNode{
+
+  //Reads an expression in prefix notation like SDF objects, every name is followed by its numbers and then its children.
+  //"checker 1 color 1 1 1 color 0 0 0" is a black and white checkerboard with 1 unit squares.
+  pub fn parse(tokens: &mut dyn Iterator<Item = &str>) -> Result<PatternNode, String> {
+    let name = tokens.next().ok_or("the pattern ends too early")?;
+    let node = match name {
+      "color" => PatternNode::Color(vector(tokens)?),
+      "value" => {
+        let v = number(tokens)?;
+        PatternNode::Color(Vector3::new(v, v, v))
+      },
+      "checker" => PatternNode::Checker(number(tokens)?, child(tokens)?, child(tokens)?),
+      "gradient" => {
+        let axis = match tokens.next() {
+          Some("x") => 0,
+          Some("y") => 1,
+          Some("z") => 2,
+          other => return Err(format!("a gradient runs along x, y or z, not '{}'", other.unwrap_or(""))),
+        };
+        PatternNode::Gradient(axis, number(tokens)?, number(tokens)?, child(tokens)?, child(tokens)?)
+      },
+      "perlin" => PatternNode::Perlin(number(tokens)?),
+      "simplex" => PatternNode::Simplex(number(tokens)?),
+      "fbm" => PatternNode::Fbm(number(tokens)?, number(tokens)? as u32),
+      "worley" => PatternNode::Worley(number(tokens)?),
+      "marble" => PatternNode::Marble(number(tokens)?, number(tokens)?, child(tokens)?, child(tokens)?),
+      "wood" => PatternNode::Wood(number(tokens)?, number(tokens)?, child(tokens)?, child(tokens)?),
+      "mix" => PatternNode::Mix(child(tokens)?, child(tokens)?, child(tokens)?),
+      "multiply" => PatternNode::Multiply(child(tokens)?, child(tokens)?),
+      "translate" => PatternNode::Translate(vector(tokens)?, child(tokens)?),
+      "scale" => PatternNode::Scale(number(tokens)?, child(tokens)?),
+      _ => return Err(format!("unknown pattern '{}'", name)),
+    };
+    Ok(node)
+  }
+
+  pub fn color(&self, p: Vector3) -> Vector3 {
+    match self {
+      PatternNode::Color(c) => *c,
+      PatternNode::Checker(size, a, b) => {
+        let cell = (p.x/size).floor() + (p.y/size).floor() + (p.z/size).floor();
+        if (cell as i64).rem_euclid(2) == 0 {a.color(p)} else {b.color(p)}
+      },
+      PatternNode::Gradient(axis, start, end, a, b) => {
+        let along = [p.x, p.y, p.z][*axis];
+        let t = ((along - start)/(end - start)).max(0.0).min(1.0);
+        a.color(p)*(1.0 - t) + b.color(p)*t
+      },
+      PatternNode::Perlin(scale) => grey(perlin(p*(*scale))*0.5 + 0.5),
+      PatternNode::Simplex(scale) => grey(simplex(p*(*scale))*0.5 + 0.5),
+      PatternNode::Fbm(scale, octaves) => grey(fbm(p*(*scale), *octaves)*0.5 + 0.5),
+      PatternNode::Worley(scale) => grey(worley(p*(*scale)).min(1.0)),
+      //Veins are a sine wave across x, pushed around by noise (turbulence is how far, in units of the pattern's space).
+      PatternNode::Marble(scale, turbulence, a, b) => {
+        let t = 0.5 + 0.5*((p.x + turbulence*fbm(p, 5))*scale).sin();
+        a.color(p)*(1.0 - t) + b.color(p)*t
+      },
+      //Rings around the y axis, wobbled by noise, with the light early wood fading into the dark late wood.
+      PatternNode::Wood(scale, turbulence, a, b) => {
+        let rings = ((p.x*p.x + p.z*p.z).sqrt() + turbulence*fbm(p, 3))*scale;
+        let t = (rings - rings.floor()).powf(3.0);
+        a.color(p)*(1.0 - t) + b.color(p)*t
+      },
+      PatternNode::Mix(t, a, b) => {
+        let t = t.value(p).max(0.0).min(1.0);
+        a.color(p)*(1.0 - t) + b.color(p)*t
+      },
+      PatternNode::Multiply(a, b) => {
+        let (a, b) = (a.color(p), b.color(p));
+        Vector3::new(a.x*b.x, a.y*b.y, a.z*b.z)
+      },
+      PatternNode::Translate(offset, a) => a.color(p - *offset),
+      PatternNode::Scale(scale, a) => a.color(p*(1.0/scale)),
+    }
+  }
+
+  //The pattern as a single number, for roughness, bumps and mixing.
+  pub fn value(&self, p: Vector3) -> f32 {
+    let c = self.color(p);
+    (c.x + c.y + c.z)/3.0
+  }
+
+}
+
+impl Pattern{
+
+  //Solid patterns are looked up just under the surface, so a face lying on a cell border (like a checker floor at y = 0) doesn't flicker between cells.
+  fn point(&self, hit: &Hit, frame: &Frame) -> Vector3 {
+    let inside = hit.point - hit.normal*1e-4;
+    match self.space {
+      Space::Object => frame.to_local(inside),
+      Space::World => inside,
+      Space::Uv => Vector3::new(hit.uv.x, hit.uv.y, 0.0),
+    }
+  }
+
+}
+
+//Fills in what the material's patterns give at a hit: the diffuse color, the roughness and the bumps.
+//Roughness (0 to 1) becomes the specular exponent the same way glTF materials do.
+pub fn apply_patterns(hit: &mut Hit, frame: &Frame) {
+  //The patterns are shared, so holding on to them costs nothing while the hit's own material is changed.
+  let (color, roughness, bump) = (hit.material.color_pattern.clone(), hit.material.roughness_pattern.clone(), hit.material.bump_pattern.clone());
+  if let Some(pattern) = color {
+    hit.material.diffuse_color = pattern.root.color(pattern.point(hit, frame));
+  }
+  if let Some(pattern) = roughness {
+    hit.material.specular_exponent = Material::roughness_exponent(pattern.root.value(pattern.point(hit, frame)));
+  }
+  if let Some(pattern) = bump {
+    //The slope of the pattern a small step to each side, in its own space, then turned into the world.
+    let p = pattern.point(hit, frame);
+    let e = 1e-3;
+    let slope = |d: Vector3| (pattern.root.value(p + d) - pattern.root.value(p - d))/(2.0*e);
+    let local = Vector3::new(slope(Vector3::new(e, 0.0, 0.0)), slope(Vector3::new(0.0, e, 0.0)), slope(Vector3::new(0.0, 0.0, e)));
+    let gradient = match pattern.space {
+      Space::Object => frame.gradient_to_world(local),
+      Space::World => local,
+      Space::Uv => {
+        let (tangent, bitangent) = tangent_basis(hit.normal, hit.tangent);
+        tangent*local.x + bitangent*local.y
+      },
+    };
+    let normal = hit.normal;
+    let along_surface = gradient - normal*normal.dot(&gradient);
+    hit.normal = (normal - along_surface*hit.material.pattern_bump_scale).normalize();
+  }
+}
+
+fn grey(v: f32) -> Vector3 {
+  Vector3::new(v, v, v)
+}
+
+//A pseudo random number for a lattice point, the same point always gives the same number.
+fn lattice(x: i32, y: i32, z: i32) -> u32 {
+  hash((x as u32).wrapping_add(hash((y as u32).wrapping_add(hash(z as u32)))))
+}
+
+//One of the 12 edge directions of a cube, dotted with the offset, as in Perlin's improved noise.
+fn gradient(h: u32, x: f32, y: f32, z: f32) -> f32 {
+  match h % 12 {
+    0 => x + y,
+    1 => -x + y,
+    2 => x - y,
+    3 => -x - y,
+    4 => x + z,
+    5 => -x + z,
+    6 => x - z,
+    7 => -x - z,
+    8 => y + z,
+    9 => -y + z,
+    10 => y - z,
+    _ => -y - z,
+  }
+}
+
+fn fade(t: f32) -> f32 {
+  t*t*t*(t*(t*6.0 - 15.0) + 10.0)
+}
+
+//Perlin's improved gradient noise, roughly -1 to 1.
+pub fn perlin(p: Vector3) -> f32 {
+  let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+  let (x, y, z) = (fx as i32, fy as i32, fz as i32);
+  let (dx, dy, dz) = (p.x - fx, p.y - fy, p.z - fz);
+  let (u, v, w) = (fade(dx), fade(dy), fade(dz));
+  let corner = |i: i32, j: i32, k: i32| gradient(lattice(x + i, y + j, z + k), dx - i as f32, dy - j as f32, dz - k as f32);
+  let mix = |a: f32, b: f32, t: f32| a + (b - a)*t;
+  mix(
+    mix(mix(corner(0, 0, 0), corner(1, 0, 0), u), mix(corner(0, 1, 0), corner(1, 1, 0), u), v),
+    mix(mix(corner(0, 0, 1), corner(1, 0, 1), u), mix(corner(0, 1, 1), corner(1, 1, 1), u), v),
+    w,
+  )
+}
+
+//3D simplex noise (after Stefan Gustavson's notes), roughly -1 to 1 and without the grid look of Perlin noise.
+pub fn simplex(p: Vector3) -> f32 {
+  let skew = (p.x + p.y + p.z)/3.0;
+  let (i, j, k) = ((p.x + skew).floor(), (p.y + skew).floor(), (p.z + skew).floor());
+  let unskew = (i + j + k)/6.0;
+  let x0 = Vector3::new(p.x - i + unskew, p.y - j + unskew, p.z - k + unskew);
+  //Which of the six tetrahedra of the skewed cube the point is in.
+  let (first, second) = if x0.x >= x0.y {
+    if x0.y >= x0.z {((1, 0, 0), (1, 1, 0))} else if x0.x >= x0.z {((1, 0, 0), (1, 0, 1))} else {((0, 0, 1), (1, 0, 1))}
+  }
+  else {
+    if x0.y < x0.z {((0, 0, 1), (0, 1, 1))} else if x0.x < x0.z {((0, 1, 0), (0, 1, 1))} else {((0, 1, 0), (1, 1, 0))}
+  };
+  let (i, j, k) = (i as i32, j as i32, k as i32);
+  let mut total = 0.0;
+  for (n, &(a, b, c)) in [(0, 0, 0), first, second, (1, 1, 1)].iter().enumerate() {
+    let offset = n as f32/6.0;
+    let d = Vector3::new(x0.x - a as f32 + offset, x0.y - b as f32 + offset, x0.z - c as f32 + offset);
+    let t = 0.6 - d.dot(&d);
+    if t > 0.0 {
+      total += t*t*t*t*gradient(lattice(i + a, j + b, k + c), d.x, d.y, d.z);
+    }
+  }
+  return 32.0*total;
+}
+
+//Fractal Brownian motion, octaves of Perlin noise at twice the frequency and half the strength each, kept at -1 to 1.
+pub fn fbm(p: Vector3, octaves: u32) -> f32 {
+  let (mut total, mut amplitude, mut frequency, mut sum) = (0.0, 1.0, 1.0, 0.0);
+  for _ in 0..octaves.max(1) {
+    total += perlin(p*frequency)*amplitude;
+    sum += amplitude;
+    amplitude *= 0.5;
+    frequency *= 2.0;
+  }
+  return total/sum;
+}
+
+//Worley (cellular) noise, the distance to the closest of one random point per unit cell.
+pub fn worley(p: Vector3) -> f32 {
+  let (x, y, z) = (p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32);
+  let mut closest = f32::MAX;
+  for i in -1..2 {
+    for j in -1..2 {
+      for k in -1..2 {
+        let h = lattice(x + i, y + j, z + k);
+        let (h1, h2) = (hash(h), hash(h ^ 0x9e3779b9));
+        let feature = Vector3::new(
+          (x + i) as f32 + (h >> 8) as f32/16777216.0,
+          (y + j) as f32 + (h1 >> 8) as f32/16777216.0,
+          (z + k) as f32 + (h2 >> 8) as f32/16777216.0,
+        );
+        closest = closest.min((feature - p).magnitude());
+      }
+    }
+  }
+  return closest;
+}
+
+fn number(tokens: &mut dyn Iterator<Item = &str>) -> Result<f32, String>{
+  let token = tokens.next().ok_or("the pattern ends too early")?;
+  token.parse().map_err(|_| format!("expected a number in the pattern but found '{}'", token))
+}
+
+fn vector(tokens: &mut dyn Iterator<Item = &str>) -> Result<Vector3, String>{
+  Ok(Vector3::new(number(tokens)?, number(tokens)?, number(tokens)?))
+}
+
+fn child(tokens: &mut dyn Iterator<Item = &str>) -> Result<Box<PatternNode>, String>{
+  Ok(Box::new(PatternNode::parse(tokens)?))
+}
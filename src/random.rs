@@ -0,0 +1,48 @@
+//A small xorshift random number generator, we don't pull in any crates for this.
+#[derive(Debug, Copy, Clone)]
+pub struct Rng{
+  pub state: u32,
+}
+
+impl Rng{
+
+  //Seeds the generator, the seed gets scrambled first so neighbouring pixels don't correlate.
+  pub fn new(seed: u32) -> Self {
+    let mut state = hash(seed);
+    if state == 0 {
+      state = 0x9e3779b9;
+    }
+    Rng {state}
+  }
+
+  pub fn next_u32(&mut self) -> u32 {
+    let mut x = self.state;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    self.state = x;
+    x
+  }
+
+  //Returns a float in [0, 1).
+  pub fn next_f32(&mut self) -> f32 {
+    (self.next_u32() >> 8) as f32 / 16777216.0
+  }
+
+}
+
+//Integer hash (lowbias32) used to turn pixel indices into seeds.
+pub fn hash(x: u32) -> u32 {
+  let mut x = x;
+  x ^= x >> 16;
+  x = x.wrapping_mul(0x7feb352d);
+  x ^= x >> 15;
+  x = x.wrapping_mul(0x846ca68b);
+  x ^= x >> 16;
+  x
+}
+
+//Van der Corput sequence, the bits of i mirrored behind the binary point (evenly fills [0, 1) for any number of samples).
+pub fn radical_inverse(i: u32) -> f32 {
+  (i.reverse_bits() >> 8) as f32 / 16777216.0
+}
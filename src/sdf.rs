@@ -0,0 +1,226 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Vector2;
+use definitions::Matrix3;
+use definitions::Material;
+use definitions::Ray;
+
+//A signed distance function built from primitives and combinators, negative inside the shape.
+#[derive(Debug, Clone)]
+pub enum SdfNode{
+  Sphere(Vector3, f32),
+  Box(Vector3, Vector3),
+  RoundBox(Vector3, Vector3, f32),
+  Torus(Vector3, f32, f32),
+  Cylinder(Vector3, f32, f32),
+  Capsule(Vector3, Vector3, f32),
+  Plane(Vector3, f32),
+  Mandelbulb(Vector3, f32, f32, u32),
+  Union(Box<SdfNode>, Box<SdfNode>),
+  Intersection(Box<SdfNode>, Box<SdfNode>),
+  Difference(Box<SdfNode>, Box<SdfNode>),
+  SmoothUnion(f32, Box<SdfNode>, Box<SdfNode>),
+  SmoothIntersection(f32, Box<SdfNode>, Box<SdfNode>),
+  SmoothDifference(f32, Box<SdfNode>, Box<SdfNode>),
+  Translate(Vector3, Box<SdfNode>),
+  Rotate(Matrix3, Box<SdfNode>),
+  Scale(f32, Box<SdfNode>),
+  Round(f32, Box<SdfNode>),
+  Onion(f32, Box<SdfNode>),
+  Repeat(Vector3, Box<SdfNode>),
+}
+
+impl SdfNode{
+
+  //Reads an expression in prefix notation, every name is followed by its numbers and then its children.
+  //"smooth_union 0.5 sphere 0 0 0 1 box 1 0 0 0.5 0.5 0.5" blends a sphere into a box.
+  pub fn parse(tokens: &mut dyn Iterator<Item = &str>) -> Result<SdfNode, String> {
+    let name = tokens.next().ok_or("the SDF expression ends too early")?;
+    let node = match name {
+      "sphere" => SdfNode::Sphere(vector(tokens)?, number(tokens)?),
+      "box" => SdfNode::Box(vector(tokens)?, vector(tokens)?),
+      "round_box" => SdfNode::RoundBox(vector(tokens)?, vector(tokens)?, number(tokens)?),
+      "torus" => SdfNode::Torus(vector(tokens)?, number(tokens)?, number(tokens)?),
+      "cylinder" => SdfNode::Cylinder(vector(tokens)?, number(tokens)?, number(tokens)?),
+      "capsule" => SdfNode::Capsule(vector(tokens)?, vector(tokens)?, number(tokens)?),
+      "plane" => SdfNode::Plane(vector(tokens)?.normalize(), number(tokens)?),
+      "mandelbulb" => SdfNode::Mandelbulb(vector(tokens)?, number(tokens)?, number(tokens)?, number(tokens)? as u32),
+      "union" => SdfNode::Union(child(tokens)?, child(tokens)?),
+      "intersection" => SdfNode::Intersection(child(tokens)?, child(tokens)?),
+      "difference" => SdfNode::Difference(child(tokens)?, child(tokens)?),
+      "smooth_union" => SdfNode::SmoothUnion(number(tokens)?, child(tokens)?, child(tokens)?),
+      "smooth_intersection" => SdfNode::SmoothIntersection(number(tokens)?, child(tokens)?, child(tokens)?),
+      "smooth_difference" => SdfNode::SmoothDifference(number(tokens)?, child(tokens)?, child(tokens)?),
+      "translate" => SdfNode::Translate(vector(tokens)?, child(tokens)?),
+      //Rotates around the origin, so rotate a shape at the origin and translate it afterwards.
+      "rotate" => SdfNode::Rotate(Matrix3::rotation(vector(tokens)?), child(tokens)?),
+      "scale" => SdfNode::Scale(number(tokens)?, child(tokens)?),
+      "round" => SdfNode::Round(number(tokens)?, child(tokens)?),
+      "onion" => SdfNode::Onion(number(tokens)?, child(tokens)?),
+      "repeat" => SdfNode::Repeat(vector(tokens)?, child(tokens)?),
+      _ => return Err(format!("unknown SDF '{}'", name)),
+    };
+    Ok(node)
+  }
+
+  pub fn distance(&self, p: Vector3) -> f32 {
+    match self {
+      SdfNode::Sphere(center, radius) => (p - *center).magnitude() - radius,
+      SdfNode::Box(center, half) => box_distance(p - *center, *half),
+      SdfNode::RoundBox(center, half, radius) => box_distance(p - *center, *half - Vector3::new(*radius, *radius, *radius)) - radius,
+      SdfNode::Torus(center, major, minor) => {
+        let q = p - *center;
+        let ring = (q.x*q.x + q.z*q.z).sqrt() - major;
+        (ring*ring + q.y*q.y).sqrt() - minor
+      },
+      SdfNode::Cylinder(center, radius, height) => {
+        let q = p - *center;
+        let dx = (q.x*q.x + q.z*q.z).sqrt() - radius;
+        let dy = q.y.abs() - height*0.5;
+        f32::min(f32::max(dx, dy), 0.0) + (dx.max(0.0)*dx.max(0.0) + dy.max(0.0)*dy.max(0.0)).sqrt()
+      },
+      SdfNode::Capsule(a, b, radius) => {
+        let pa = p - *a;
+        let ba = *b - *a;
+        let h = (pa.dot(&ba)/ba.dot(&ba)).max(0.0).min(1.0);
+        (pa - ba*h).magnitude() - radius
+      },
+      SdfNode::Plane(normal, height) => p.dot(normal) - height,
+      SdfNode::Mandelbulb(center, scale, power, iterations) => mandelbulb((p - *center)*(1.0/scale), *power, *iterations)*scale,
+      SdfNode::Union(a, b) => f32::min(a.distance(p), b.distance(p)),
+      SdfNode::Intersection(a, b) => f32::max(a.distance(p), b.distance(p)),
+      SdfNode::Difference(a, b) => f32::max(a.distance(p), -b.distance(p)),
+      SdfNode::SmoothUnion(k, a, b) => {
+        let (a, b) = (a.distance(p), b.distance(p));
+        let h = (0.5 + 0.5*(b - a)/k).max(0.0).min(1.0);
+        b + (a - b)*h - k*h*(1.0 - h)
+      },
+      SdfNode::SmoothIntersection(k, a, b) => {
+        let (a, b) = (a.distance(p), b.distance(p));
+        let h = (0.5 - 0.5*(b - a)/k).max(0.0).min(1.0);
+        b + (a - b)*h + k*h*(1.0 - h)
+      },
+      SdfNode::SmoothDifference(k, a, b) => {
+        let (a, b) = (a.distance(p), b.distance(p));
+        let h = (0.5 - 0.5*(a + b)/k).max(0.0).min(1.0);
+        a + (-b - a)*h + k*h*(1.0 - h)
+      },
+      SdfNode::Translate(offset, a) => a.distance(p - *offset),
+      SdfNode::Rotate(rotation, a) => a.distance(rotation.transpose().apply(p)),
+      SdfNode::Scale(s, a) => a.distance(p*(1.0/s))*s,
+      SdfNode::Round(r, a) => a.distance(p) - r,
+      SdfNode::Onion(thickness, a) => a.distance(p).abs() - thickness,
+      SdfNode::Repeat(period, a) => {
+        //A period of 0 leaves that axis alone.
+        let wrap = |v: f32, c: f32| if c > 0.0 {v - c*(v/c).round()} else {v};
+        a.distance(Vector3::new(wrap(p.x, period.x), wrap(p.y, period.y), wrap(p.z, period.z)))
+      },
+    }
+  }
+
+}
+
+//One SDF object in the scene, with the settings its sphere tracing was given in the script.
+#[derive(Debug, Clone)]
+pub struct Sdf{
+  pub root: SdfNode,
+  pub material: Material,
+  pub steps: u32,
+  pub epsilon: f32,
+  pub max_distance: f32,
+  pub id: u32,
+}
+
+impl Sdf{
+
+  pub fn new(root: SdfNode, material: Material, steps: u32, epsilon: f32, max_distance: f32, id: u32) -> Self {
+    Sdf {root, material, steps, epsilon, max_distance, id}
+  }
+
+  //Central differences, the normal points to where the distance grows.
+  fn normal(&self, p: Vector3) -> Vector3 {
+    let h = self.epsilon;
+    let dx = Vector3::new(h, 0.0, 0.0);
+    let dy = Vector3::new(0.0, h, 0.0);
+    let dz = Vector3::new(0.0, 0.0, h);
+    Vector3::new(
+      self.root.distance(p + dx) - self.root.distance(p - dx),
+      self.root.distance(p + dy) - self.root.distance(p - dy),
+      self.root.distance(p + dz) - self.root.distance(p - dz),
+    ).normalize()
+  }
+
+}
+
+//Sphere tracing: the distance to the surface is always a safe step, so we keep stepping until it is tiny.
+//Rays that start inside (refraction) trace the negated field, so they find the way out.
+pub fn sdf_intersect(sdf: &Sdf, ray: Ray) -> Option<(f32, Vector3, Vector2)>{
+  let mut t = 0.0;
+  let mut steps = sdf.steps;
+  //Shadow and reflection rays start just off the surface, which can still be closer than epsilon.
+  //They march off it first so they don't find the surface they left, like the analytic primitives never hit at t = 0.
+  while steps > 0 && sdf.root.distance(ray.origin + ray.direction*t).abs() < sdf.epsilon{
+    t += sdf.epsilon;
+    steps -= 1;
+  }
+  let side = if sdf.root.distance(ray.origin + ray.direction*t) < 0.0 {-1.0} else {1.0};
+  for _ in 0..steps{
+    let p = ray.origin + ray.direction*t;
+    let distance = sdf.root.distance(p)*side;
+    if distance < sdf.epsilon{
+      return Some((t, sdf.normal(p), Vector2::new(0.0, 0.0)));
+    }
+    t += distance;
+    if t > sdf.max_distance{
+      break;
+    }
+  }
+  return None;
+}
+
+fn box_distance(p: Vector3, half: Vector3) -> f32{
+  let q = Vector3::new(p.x.abs() - half.x, p.y.abs() - half.y, p.z.abs() - half.z);
+  let outside = Vector3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).magnitude();
+  outside + f32::min(q.x.max(q.y).max(q.z), 0.0)
+}
+
+//Distance estimate of the Mandelbulb fractal (it lives within a radius of about 1.2).
+fn mandelbulb(p: Vector3, power: f32, iterations: u32) -> f32{
+  //The estimate overshoots far away from the fractal, so until we're close we step to a bounding sphere instead.
+  let bound = p.magnitude() - 1.5;
+  if bound > 0.5{
+    return bound;
+  }
+  let mut z = p;
+  let mut dr = 1.0;
+  let mut r = 0.0;
+  for _ in 0..iterations{
+    r = z.magnitude();
+    //Escaped, or sitting on the centre where the angles can't be found.
+    if r > 2.0 || r == 0.0{
+      break;
+    }
+    let theta = (z.z/r).acos()*power;
+    let phi = z.y.atan2(z.x)*power;
+    let zr = r.powf(power);
+    dr = r.powf(power - 1.0)*power*dr + 1.0;
+    z = Vector3::new(theta.sin()*phi.cos(), phi.sin()*theta.sin(), theta.cos())*zr + p;
+  }
+  if r == 0.0{
+    return 0.0;
+  }
+  0.5*r.ln()*r/dr
+}
+
+fn number(tokens: &mut dyn Iterator<Item = &str>) -> Result<f32, String>{
+  let token = tokens.next().ok_or("the SDF expression ends too early")?;
+  token.parse().map_err(|_| format!("expected a number in the SDF but found '{}'", token))
+}
+
+fn vector(tokens: &mut dyn Iterator<Item = &str>) -> Result<Vector3, String>{
+  Ok(Vector3::new(number(tokens)?, number(tokens)?, number(tokens)?))
+}
+
+fn child(tokens: &mut dyn Iterator<Item = &str>) -> Result<Box<SdfNode>, String>{
+  Ok(Box::new(SdfNode::parse(tokens)?))
+}
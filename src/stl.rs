@@ -0,0 +1,110 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Vector3i;
+
+//Import the mesh data from model.rs
+use model::MeshData;
+
+use std::collections::HashMap;
+use std::fs;
+
+//Faces that meet at a sharper angle than this (in degrees) keep a hard edge, which is what CAD parts want.
+const CREASE_ANGLE: f32 = 30.0;
+
+//One triangle as STL stores it, with the facet normal from the file (which can be all zeros).
+type Facet = (Vector3, [Vector3; 3]);
+
+//Reads an ASCII or binary STL file.
+//STL repeats every vertex for every triangle, so matching positions are welded back together before the normals are smoothed.
+pub fn load_stl(filename: &str) -> Result<MeshData, String> {
+  let data = fs::read(filename).map_err(|e| e.to_string())?;
+  //Binary files can start with "solid" too, so the size decides: an 80 byte header, a count and 50 bytes per triangle.
+  let binary = data.len() >= 84 && {
+    let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+    data.len() == 84 + count*50
+  };
+  let facets = if binary {
+    read_binary(&data)
+  }
+  else if data.starts_with(b"solid") {
+    read_ascii(std::str::from_utf8(&data).map_err(|_| "the ASCII data isn't valid text")?)?
+  }
+  else {
+    return Err("this isn't an STL file (or it has been cut short)".to_string());
+  };
+
+  let mut mesh = weld(&facets);
+  mesh.smooth_normals(CREASE_ANGLE);
+  return Ok(mesh);
+}
+
+fn read_binary(data: &[u8]) -> Vec<Facet> {
+  let float = |at: usize| f32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+  let vector = |at: usize| Vector3::new(float(at), float(at + 4), float(at + 8));
+  let count = (data.len() - 84)/50;
+  (0..count).map(|i| {
+    let at = 84 + i*50;
+    //The two bytes after the vertices are an attribute count that nobody uses.
+    (vector(at), [vector(at + 12), vector(at + 24), vector(at + 36)])
+  }).collect()
+}
+
+fn read_ascii(text: &str) -> Result<Vec<Facet>, String> {
+  let mut facets: Vec<Facet> = Vec::new();
+  let mut tokens = text.split_ascii_whitespace();
+  let mut normal = Vector3::new(0.0, 0.0, 0.0);
+  let mut corners: Vec<Vector3> = Vec::new();
+  while let Some(token) = tokens.next() {
+    match token {
+      "facet" => {
+        if tokens.next() != Some("normal") {
+          return Err("'facet' isn't followed by 'normal'".to_string());
+        }
+        normal = vector(&mut tokens)?;
+        corners.clear();
+      },
+      "vertex" => corners.push(vector(&mut tokens)?),
+      //Some exporters write polygons, they are split into triangles like OBJ faces.
+      "endfacet" => for i in 2..corners.len() {
+        facets.push((normal, [corners[0], corners[i - 1], corners[i]]));
+      },
+      _ => (),
+    }
+  }
+  return Ok(facets);
+}
+
+fn vector<'a>(tokens: &mut dyn Iterator<Item = &'a str>) -> Result<Vector3, String> {
+  let mut number = || -> Result<f32, String> {
+    let token = tokens.next().ok_or("the file ends too early")?;
+    token.parse().map_err(|_| format!("expected a number but found '{}'", token))
+  };
+  Ok(Vector3::new(number()?, number()?, number()?))
+}
+
+//Positions that are exactly the same become one vertex, triangles that collapse when that happens are dropped.
+//A facet normal that disagrees with the winding turns the triangle around, files from some exporters need that.
+fn weld(facets: &Vec<Facet>) -> MeshData {
+  let mut mesh = MeshData::new(Vec::new(), Vec::new());
+  let mut indices: HashMap<[u32; 3], i32> = HashMap::new();
+  for (normal, corners) in facets {
+    let mut face = [0; 3];
+    for (k, p) in corners.iter().enumerate() {
+      //Adding 0.0 turns -0.0 into 0.0 so they weld together.
+      let key = [(p.x + 0.0).to_bits(), (p.y + 0.0).to_bits(), (p.z + 0.0).to_bits()];
+      face[k] = *indices.entry(key).or_insert_with(|| {
+        mesh.verts.push(*p);
+        mesh.verts.len() as i32 - 1
+      });
+    }
+    if face[0] == face[1] || face[1] == face[2] || face[0] == face[2] {
+      continue;
+    }
+    let winding = (corners[1] - corners[0]).cross(&(corners[2] - corners[0]));
+    if winding.dot(normal) < 0.0 {
+      face.swap(1, 2);
+    }
+    mesh.faces.push(Vector3i::new(face[0], face[1], face[2]));
+  }
+  return mesh;
+}
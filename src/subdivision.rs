@@ -0,0 +1,318 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Vector3i;
+use definitions::Vector2;
+
+//Import the mesh loading from model.rs
+use model::{MeshData, load_mesh, parse_obj};
+
+use std::collections::HashMap;
+use std::path::Path;
+
+//How the cage is smoothed, Auto uses Catmull-Clark when there are polygons other than triangles and Loop otherwise.
+//Linear splits the faces the same way without moving anything, for displacement on flat meshes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Scheme{
+  Auto,
+  CatmullClark,
+  Loop,
+  Linear,
+}
+
+impl Scheme{
+
+  pub fn from_name(name: &str) -> Option<Scheme> {
+    match name {
+      "auto" => Some(Scheme::Auto),
+      "catmull-clark" | "cc" => Some(Scheme::CatmullClark),
+      "loop" => Some(Scheme::Loop),
+      "linear" => Some(Scheme::Linear),
+      _ => None,
+    }
+  }
+
+}
+
+//A polygon of the cage, with a UV for every corner (or none) and the smoothing group it's in.
+#[derive(Debug, Clone)]
+pub struct CageFace{
+  pub corners: Vec<usize>,
+  pub uvs: Vec<Vector2>,
+  pub group: u32,
+}
+
+//The control mesh that gets subdivided, polygons are kept whole and UVs belong to the face corners so seams survive.
+#[derive(Debug, Clone)]
+pub struct Cage{
+  pub verts: Vec<Vector3>,
+  pub colors: Vec<Vector3>,
+  pub faces: Vec<CageFace>,
+}
+
+//A new vertex as a weighted sum of the vertices of the level before.
+type Stencil = Vec<(usize, f32)>;
+
+//Loads a mesh and subdivides it, OBJ files keep their polygons and smoothing groups.
+//Other formats only have triangles, their vertices are welded by position and all of their edges are smooth.
+pub fn load_subdivided(filename: &str, groups: &Vec<String>, scheme: Scheme, levels: u32) -> MeshData {
+  let extension = Path::new(filename).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+  let cage = if extension == "obj" {
+    match Cage::from_obj(filename, groups) {
+      Ok(cage) => cage,
+      Err(err) => {
+        eprintln!("Failed to load {}: {}", filename, err);
+        return MeshData::new(Vec::new(), Vec::new());
+      }
+    }
+  }
+  else {
+    Cage::from_mesh(&load_mesh(filename, groups))
+  };
+  return cage.subdivide(scheme, levels).to_mesh();
+}
+
+impl Cage{
+
+  pub fn from_obj(filename: &str, groups: &Vec<String>) -> Result<Cage, String> {
+    let obj = parse_obj(filename, groups)?;
+    let with_uvs = obj.polygons.iter().all(|(corners, _)| corners.iter().all(|c| c.1.is_some()));
+    let faces = obj.polygons.iter().map(|(corners, group)| CageFace {
+      corners: corners.iter().map(|c| c.0).collect(),
+      uvs: if with_uvs {corners.iter().map(|c| obj.uvs[c.1.unwrap()]).collect()} else {Vec::new()},
+      group: *group,
+    }).collect();
+    return Ok(Cage {verts: obj.positions, colors: obj.colors, faces});
+  }
+
+  pub fn from_mesh(mesh: &MeshData) -> Cage {
+    let mut cage = Cage {verts: Vec::new(), colors: Vec::new(), faces: Vec::new()};
+    let mut welded: HashMap<[u32; 3], usize> = HashMap::new();
+    let mut index: Vec<usize> = Vec::with_capacity(mesh.verts.len());
+    for (i, p) in mesh.verts.iter().enumerate() {
+      let key = [(p.x + 0.0).to_bits(), (p.y + 0.0).to_bits(), (p.z + 0.0).to_bits()];
+      index.push(*welded.entry(key).or_insert_with(|| {
+        cage.verts.push(*p);
+        if !mesh.colors.is_empty() {
+          cage.colors.push(mesh.colors[i]);
+        }
+        cage.verts.len() - 1
+      }));
+    }
+    for f in &mesh.faces {
+      let corners = [f.x as usize, f.y as usize, f.z as usize];
+      cage.faces.push(CageFace {
+        corners: corners.iter().map(|&c| index[c]).collect(),
+        uvs: if mesh.uvs.is_empty() {Vec::new()} else {corners.iter().map(|&c| mesh.uvs[c]).collect()},
+        group: 0,
+      });
+    }
+    return cage;
+  }
+
+  //Subdivides the cage as many times as asked.
+  //Catmull-Clark turns every polygon into quads and Loop splits triangles in four (other polygons are split into triangles first).
+  pub fn subdivide(&self, scheme: Scheme, levels: u32) -> Cage {
+    let polygons = self.faces.iter().any(|f| f.corners.len() != 3);
+    let (quads, smooth) = match scheme {
+      Scheme::Auto => (polygons, true),
+      Scheme::CatmullClark => (true, true),
+      Scheme::Loop => (false, true),
+      Scheme::Linear => (polygons, false),
+    };
+    let mut cage = self.clone();
+    if !quads && polygons {
+      cage.faces = cage.faces.iter().flat_map(|f| (2..f.corners.len()).map(move |i| {
+        let pick = [0, i - 1, i];
+        CageFace {
+          corners: pick.iter().map(|&k| f.corners[k]).collect(),
+          uvs: if f.uvs.is_empty() {Vec::new()} else {pick.iter().map(|&k| f.uvs[k]).collect()},
+          group: f.group,
+        }
+      })).collect();
+    }
+    for _ in 0..levels {
+      cage = cage.step(quads, smooth);
+    }
+    return cage;
+  }
+
+  //One level of subdivision. The new vertices are the old ones moved, then one per edge, then (for Catmull-Clark) one per face.
+  //Edges on the boundary, between faces of different smoothing groups or with more than two faces are creases:
+  //they are split in the middle and the vertices along them only follow the crease, where three or more creases meet the vertex stays put.
+  fn step(&self, quads: bool, smooth: bool) -> Cage {
+    let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut edge_ends: Vec<(usize, usize)> = Vec::new();
+    let mut edge_faces: Vec<Vec<usize>> = Vec::new();
+    let mut vertex_edges: Vec<Vec<usize>> = vec![Vec::new(); self.verts.len()];
+    let mut vertex_faces: Vec<Vec<usize>> = vec![Vec::new(); self.verts.len()];
+    for (i, f) in self.faces.iter().enumerate() {
+      for k in 0..f.corners.len() {
+        let (a, b) = (f.corners[k], f.corners[(k + 1) % f.corners.len()]);
+        let e = *edges.entry((a.min(b), a.max(b))).or_insert_with(|| {
+          edge_ends.push((a, b));
+          edge_faces.push(Vec::new());
+          vertex_edges[a].push(edge_ends.len() - 1);
+          vertex_edges[b].push(edge_ends.len() - 1);
+          edge_ends.len() - 1
+        });
+        edge_faces[e].push(i);
+        vertex_faces[a].push(i);
+      }
+    }
+    let sharp: Vec<bool> = edge_faces.iter().map(|faces| {
+      !smooth || faces.len() != 2 || self.faces[faces[0]].group != self.faces[faces[1]].group
+    }).collect();
+    let face_point = |f: usize| -> Stencil {
+      let corners = &self.faces[f].corners;
+      corners.iter().map(|&c| (c, 1.0/corners.len() as f32)).collect()
+    };
+    let other = |e: usize, v: usize| if edge_ends[e].0 == v {edge_ends[e].1} else {edge_ends[e].0};
+
+    let mut stencils: Vec<Stencil> = Vec::with_capacity(self.verts.len() + edge_ends.len() + self.faces.len());
+    for v in 0..self.verts.len() {
+      let n = vertex_edges[v].len();
+      let creases: Vec<usize> = vertex_edges[v].iter().cloned().filter(|&e| sharp[e]).collect();
+      //Corners of the cage (vertices with only two edges) stay where they are so flat patches keep their shape.
+      if !smooth || n <= 2 || creases.len() > 2 {
+        stencils.push(vec![(v, 1.0)]);
+      }
+      else if creases.len() == 2 {
+        stencils.push(vec![(v, 0.75), (other(creases[0], v), 0.125), (other(creases[1], v), 0.125)]);
+      }
+      else if quads {
+        //(Q + 2R + (n - 3)P)/n, with Q the average of the face points around it and R the average of the edge middles.
+        let n = n as f32;
+        let mut stencil: Stencil = vec![(v, (n - 3.0)/n)];
+        let faces = &vertex_faces[v];
+        for &f in faces {
+          stencil.extend(face_point(f).into_iter().map(|(i, w)| (i, w/(faces.len() as f32*n))));
+        }
+        for &e in &vertex_edges[v] {
+          stencil.push((v, 1.0/(n*n)));
+          stencil.push((other(e, v), 1.0/(n*n)));
+        }
+        stencils.push(stencil);
+      }
+      else {
+        //Warren's weights for Loop subdivision.
+        let beta = if n == 3 {3.0/16.0} else {3.0/(8.0*n as f32)};
+        let mut stencil: Stencil = vec![(v, 1.0 - n as f32*beta)];
+        stencil.extend(vertex_edges[v].iter().map(|&e| (other(e, v), beta)));
+        stencils.push(stencil);
+      }
+    }
+    for (e, &(a, b)) in edge_ends.iter().enumerate() {
+      if sharp[e] {
+        stencils.push(vec![(a, 0.5), (b, 0.5)]);
+      }
+      else if quads {
+        let mut stencil: Stencil = vec![(a, 0.25), (b, 0.25)];
+        for &f in &edge_faces[e] {
+          stencil.extend(face_point(f).into_iter().map(|(i, w)| (i, w*0.25)));
+        }
+        stencils.push(stencil);
+      }
+      else {
+        let mut stencil: Stencil = vec![(a, 0.375), (b, 0.375)];
+        for &f in &edge_faces[e] {
+          let opposite = self.faces[f].corners.iter().cloned().find(|&c| c != a && c != b).unwrap_or(a);
+          stencil.push((opposite, 0.125));
+        }
+        stencils.push(stencil);
+      }
+    }
+    let first_face_point = stencils.len();
+    if quads {
+      for f in 0..self.faces.len() {
+        stencils.push(face_point(f));
+      }
+    }
+
+    let mix = |values: &Vec<Vector3>, stencil: &Stencil| stencil.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, &(i, w)| sum + values[i]*w);
+    let verts = stencils.iter().map(|s| mix(&self.verts, s)).collect();
+    let colors = if self.colors.is_empty() {Vec::new()} else {stencils.iter().map(|s| mix(&self.colors, s)).collect()};
+
+    let edge_point = |a: usize, b: usize| self.verts.len() + edges[&(a.min(b), a.max(b))];
+    let middle = |a: Vector2, b: Vector2| Vector2::new((a.x + b.x)*0.5, (a.y + b.y)*0.5);
+    let mut faces: Vec<CageFace> = Vec::new();
+    for (i, f) in self.faces.iter().enumerate() {
+      let k = f.corners.len();
+      let c = &f.corners;
+      //The UVs are blended straight across the face, so seams and texture layouts stay as they were.
+      let uv_middle = |j: usize| middle(f.uvs[j], f.uvs[(j + 1) % k]);
+      if quads {
+        let center = f.uvs.iter().fold(Vector2::new(0.0, 0.0), |sum, uv| Vector2::new(sum.x + uv.x/k as f32, sum.y + uv.y/k as f32));
+        for j in 0..k {
+          let previous = (j + k - 1) % k;
+          faces.push(CageFace {
+            corners: vec![c[j], edge_point(c[j], c[(j + 1) % k]), first_face_point + i, edge_point(c[previous], c[j])],
+            uvs: if f.uvs.is_empty() {Vec::new()} else {vec![f.uvs[j], uv_middle(j), center, uv_middle(previous)]},
+            group: f.group,
+          });
+        }
+      }
+      else {
+        let (ab, bc, ca) = (edge_point(c[0], c[1]), edge_point(c[1], c[2]), edge_point(c[2], c[0]));
+        let corners = [[c[0], ab, ca], [ab, c[1], bc], [ca, bc, c[2]], [ab, bc, ca]];
+        let uv_corners = [[0, 3, 5], [3, 1, 4], [5, 4, 2], [3, 4, 5]];
+        let uvs: Vec<Vector2> = if f.uvs.is_empty() {Vec::new()} else {vec![f.uvs[0], f.uvs[1], f.uvs[2], uv_middle(0), uv_middle(1), uv_middle(2)]};
+        for (corners, picks) in corners.iter().zip(uv_corners.iter()) {
+          faces.push(CageFace {
+            corners: corners.to_vec(),
+            uvs: if uvs.is_empty() {Vec::new()} else {picks.iter().map(|&p| uvs[p]).collect()},
+            group: f.group,
+          });
+        }
+      }
+    }
+    return Cage {verts, colors, faces};
+  }
+
+  //Splits the polygons into triangles for rendering.
+  //Normals are averaged over the faces around a vertex that are in the same smoothing group, so creases stay sharp in the shading too.
+  pub fn to_mesh(&self) -> MeshData {
+    let face_normals: Vec<Vector3> = self.faces.iter().map(|f| {
+      //Newell's method, which also works for polygons that aren't quite flat.
+      let mut normal = Vector3::new(0.0, 0.0, 0.0);
+      for k in 0..f.corners.len() {
+        let (a, b) = (self.verts[f.corners[k]], self.verts[f.corners[(k + 1) % f.corners.len()]]);
+        normal = normal + Vector3::new((a.y - b.y)*(a.z + b.z), (a.z - b.z)*(a.x + b.x), (a.x - b.x)*(a.y + b.y));
+      }
+      normal
+    }).collect();
+    let mut normals: HashMap<(usize, u32), Vector3> = HashMap::new();
+    for (f, face) in self.faces.iter().enumerate() {
+      for &v in &face.corners {
+        let sum = normals.entry((v, face.group)).or_insert(Vector3::new(0.0, 0.0, 0.0));
+        *sum = *sum + face_normals[f];
+      }
+    }
+
+    let with_uvs = !self.faces.is_empty() && self.faces.iter().all(|f| !f.uvs.is_empty());
+    let mut mesh = MeshData::new(Vec::new(), Vec::new());
+    let mut vertices: HashMap<(usize, u32, [u32; 2]), i32> = HashMap::new();
+    for face in &self.faces {
+      let mut corners: Vec<i32> = Vec::with_capacity(face.corners.len());
+      for (k, &v) in face.corners.iter().enumerate() {
+        let uv = if with_uvs {face.uvs[k]} else {Vector2::new(0.0, 0.0)};
+        let index = *vertices.entry((v, face.group, [uv.x.to_bits(), uv.y.to_bits()])).or_insert_with(|| {
+          mesh.verts.push(self.verts[v]);
+          mesh.normals.push(normals[&(v, face.group)].normalize());
+          if with_uvs {
+            mesh.uvs.push(uv);
+          }
+          if !self.colors.is_empty() {
+            mesh.colors.push(self.colors[v]);
+          }
+          mesh.verts.len() as i32 - 1
+        });
+        corners.push(index);
+      }
+      for i in 2..corners.len() {
+        mesh.faces.push(Vector3i::new(corners[0], corners[i - 1], corners[i]));
+      }
+    }
+    return mesh;
+  }
+
+}
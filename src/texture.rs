@@ -0,0 +1,186 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+use definitions::Vector2;
+use definitions::Vector4;
+use definitions::Material;
+
+//Import the tangent directions of primitives from intersect.rs
+use intersect::plane_basis;
+
+use std::fmt;
+use std::fs;
+
+//An image used as data on a surface (normals or heights), so the values aren't gamma corrected.
+//Rows are stored from the top like the framebuffer, v = 0 is the bottom of the image.
+pub struct Texture{
+  pub width: usize,
+  pub height: usize,
+  pub pixels: Vec<Vector3>,
+}
+
+//Printing every pixel of a material's texture isn't useful, just say how big it is.
+impl fmt::Debug for Texture{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Texture({}x{})", self.width, self.height)
+  }
+}
+
+impl Texture{
+
+  //Reads a PPM (P3 or P6, 8 or 16 bit) or PFM (PF or Pf) image.
+  pub fn load(filename: &str) -> Result<Texture, String> {
+    let data = fs::read(filename).map_err(|e| e.to_string())?;
+    let mut header = Header {data: &data, position: 0};
+    let magic = header.token()?;
+    let width: usize = header.number()?;
+    let height: usize = header.number()?;
+    if width == 0 || height == 0 {
+      return Err("the image is empty".to_string());
+    }
+    //The sizes come from the header, so they're checked before anything is read with them.
+    let too_large = || "the image is too large".to_string();
+    let pixel_count = width.checked_mul(height).ok_or_else(too_large)?;
+    let pixels = match magic.as_str() {
+      "P3" | "P6" => {
+        let maxval: u32 = header.number()?;
+        if maxval == 0 || maxval > 65535 {
+          return Err(format!("bad maximum value {}", maxval));
+        }
+        let scale = 1.0/maxval as f32;
+        let count = pixel_count.checked_mul(3).ok_or_else(too_large)?;
+        let values: Vec<u32> = if magic == "P3" {
+          (0..count).map(|_| header.number()).collect::<Result<_, _>>()?
+        }
+        else {
+          //A single whitespace character separates the header from the binary data.
+          let start = header.position + 1;
+          let size = if maxval > 255 {2} else {1};
+          let end = count.checked_mul(size).and_then(|length| length.checked_add(start)).ok_or_else(too_large)?;
+          let body = data.get(start..end).ok_or("the image data is cut short")?;
+          if size == 2 {body.chunks(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as u32).collect()} else {body.iter().map(|&b| b as u32).collect()}
+        };
+        values.chunks(3).map(|c| Vector3::new(c[0] as f32*scale, c[1] as f32*scale, c[2] as f32*scale)).collect()
+      },
+      "PF" | "Pf" => {
+        let channels = if magic == "PF" {3} else {1};
+        let scale: f32 = header.number()?;
+        let start = header.position + 1;
+        let end = pixel_count.checked_mul(channels*4).and_then(|length| length.checked_add(start)).ok_or_else(too_large)?;
+        let body = data.get(start..end).ok_or("the image data is cut short")?;
+        //A negative scale means little endian floats.
+        let floats: Vec<f32> = body.chunks(4).map(|b| {
+          let bytes = [b[0], b[1], b[2], b[3]];
+          if scale < 0.0 {f32::from_le_bytes(bytes)} else {f32::from_be_bytes(bytes)}
+        }).collect();
+        let mut pixels: Vec<Vector3> = Vec::with_capacity(pixel_count);
+        //PFM rows go from the bottom up.
+        for y in (0..height).rev() {
+          for p in floats[y*width*channels..(y + 1)*width*channels].chunks(channels) {
+            pixels.push(if channels == 3 {Vector3::new(p[0], p[1], p[2])} else {Vector3::new(p[0], p[0], p[0])});
+          }
+        }
+        pixels
+      },
+      _ => return Err("only PPM and PFM textures can be read".to_string()),
+    };
+    return Ok(Texture {width, height, pixels});
+  }
+
+  fn texel(&self, x: i64, y: i64) -> Vector3 {
+    let x = x.rem_euclid(self.width as i64) as usize;
+    let y = y.rem_euclid(self.height as i64) as usize;
+    return self.pixels[y*self.width + x];
+  }
+
+  //Bilinear lookup, the texture repeats outside of 0 to 1.
+  pub fn sample(&self, uv: Vector2) -> Vector3 {
+    let x = uv.x*self.width as f32 - 0.5;
+    let y = (1.0 - uv.y)*self.height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let top = self.texel(x0, y0)*(1.0 - fx) + self.texel(x0 + 1, y0)*fx;
+    let bottom = self.texel(x0, y0 + 1)*(1.0 - fx) + self.texel(x0 + 1, y0 + 1)*fx;
+    return top*(1.0 - fy) + bottom*fy;
+  }
+
+  //Height maps are read as the average of the channels, so grey images and colored ones both work.
+  pub fn height_at(&self, uv: Vector2) -> f32 {
+    let c = self.sample(uv);
+    return (c.x + c.y + c.z)/3.0;
+  }
+
+}
+
+//Just enough of a tokenizer for the text header of PPM and PFM files, with # comments.
+struct Header<'a>{
+  data: &'a [u8],
+  position: usize,
+}
+
+impl<'a> Header<'a>{
+
+  fn token(&mut self) -> Result<String, String> {
+    loop {
+      match self.data.get(self.position) {
+        Some(b'#') => while self.position < self.data.len() && self.data[self.position] != b'\n' {
+          self.position += 1;
+        },
+        Some(c) if c.is_ascii_whitespace() => self.position += 1,
+        Some(_) => break,
+        None => return Err("the file ends too early".to_string()),
+      }
+    }
+    let start = self.position;
+    while self.position < self.data.len() && !self.data[self.position].is_ascii_whitespace() {
+      self.position += 1;
+    }
+    return Ok(String::from_utf8_lossy(&self.data[start..self.position]).to_string());
+  }
+
+  fn number<T: std::str::FromStr>(&mut self) -> Result<T, String> {
+    let token = self.token()?;
+    token.parse().map_err(|_| format!("expected a number but found '{}'", token))
+  }
+
+}
+
+//The directions u and v grow in along the surface, made square to the (possibly bent) normal.
+pub fn tangent_basis(normal: Vector3, tangent: Option<Vector4>) -> (Vector3, Vector3) {
+  if let Some(t) = tangent {
+    let along = Vector3::new(t.x, t.y, t.z);
+    let tangent = (along - normal*normal.dot(&along)).normalize();
+    if tangent.magnitude() > 0.0 {
+      return (tangent, normal.cross(&tangent)*t.a);
+    }
+  }
+  return plane_basis(normal);
+}
+
+//Bends the shading normal with the material's normal and bump maps.
+//The tangent (with the direction of the bitangent in a) follows the u direction of the UVs, surfaces without one use the same directions as plane UVs.
+pub fn detail_normal(material: &Material, normal: Vector3, tangent: Option<Vector4>, uv: Vector2) -> Vector3 {
+  if material.normal_map.is_none() && material.bump_map.is_none() {
+    return normal;
+  }
+  let (tangent, bitangent) = tangent_basis(normal, tangent);
+  let mut normal = normal;
+  if let Some(map) = &material.normal_map {
+    //Tangent space normal maps store x, y and z as 0 to 1 colors, with green pointing along +v.
+    let c = map.sample(uv);
+    let strength = material.normal_strength;
+    let bent = tangent*((c.x*2.0 - 1.0)*strength) + bitangent*((c.y*2.0 - 1.0)*strength) + normal*(c.z*2.0 - 1.0).max(0.0);
+    if bent.magnitude() > 0.0 {
+      normal = bent.normalize();
+    }
+  }
+  if let Some(map) = &material.bump_map {
+    //The slope of the height map one texel to each side, in height per unit of UV.
+    let du = 1.0/map.width as f32;
+    let dv = 1.0/map.height as f32;
+    let slope_u = (map.height_at(Vector2::new(uv.x + du, uv.y)) - map.height_at(Vector2::new(uv.x - du, uv.y)))/(2.0*du);
+    let slope_v = (map.height_at(Vector2::new(uv.x, uv.y + dv)) - map.height_at(Vector2::new(uv.x, uv.y - dv)))/(2.0*dv);
+    normal = (normal - (tangent*slope_u + bitangent*slope_v)*material.bump_scale).normalize();
+  }
+  return normal;
+}
@@ -0,0 +1,107 @@
+//Import all of our structs from definitions.rs
+use definitions::Vector3;
+
+//Operators that squeeze the unbounded radiance in the framebuffer into displayable 0 to 1.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ToneMap{
+  Clamp,
+  Reinhard,
+  ExtendedReinhard(f32),
+  Aces,
+  Agx,
+}
+
+impl ToneMap{
+
+  //Parses the operator name used in scripts, the extended Reinhard white point defaults to 4.0.
+  pub fn from_name(name: &str, white: Option<f32>) -> Option<ToneMap> {
+    match name {
+      "clamp" => Some(ToneMap::Clamp),
+      "reinhard" => Some(ToneMap::Reinhard),
+      "reinhard_ext" => Some(ToneMap::ExtendedReinhard(white.unwrap_or(4.0))),
+      "aces" => Some(ToneMap::Aces),
+      "agx" => Some(ToneMap::Agx),
+      _ => None,
+    }
+  }
+
+  //Maps a linear color to display-linear 0 to 1.
+  pub fn apply(&self, color: Vector3) -> Vector3 {
+    let color = max3(color, 0.0);
+    match *self {
+      ToneMap::Clamp => min3(color, 1.0),
+      ToneMap::Reinhard => scale_luminance(color, |l| l/(1.0 + l)),
+      ToneMap::ExtendedReinhard(white) => scale_luminance(color, |l| l*(1.0 + l/(white*white))/(1.0 + l)),
+      ToneMap::Aces => aces(color),
+      ToneMap::Agx => agx(color),
+    }
+  }
+
+}
+
+fn max3(c: Vector3, v: f32) -> Vector3 {
+  Vector3::new(c.x.max(v), c.y.max(v), c.z.max(v))
+}
+
+fn min3(c: Vector3, v: f32) -> Vector3 {
+  Vector3::new(c.x.min(v), c.y.min(v), c.z.min(v))
+}
+
+//Rec. 709 luminance.
+pub fn luminance(c: Vector3) -> f32 {
+  0.2126*c.x + 0.7152*c.y + 0.0722*c.z
+}
+
+//Compresses the luminance and scales the color with it, which keeps the hue intact.
+fn scale_luminance<F: Fn(f32) -> f32>(color: Vector3, curve: F) -> Vector3 {
+  let l = luminance(color);
+  if l <= 0.0 {
+    return color;
+  }
+  return min3(color*(curve(l)/l), 1.0);
+}
+
+fn mat3(m: [[f32; 3]; 3], c: Vector3) -> Vector3 {
+  Vector3::new(
+    m[0][0]*c.x + m[0][1]*c.y + m[0][2]*c.z,
+    m[1][0]*c.x + m[1][1]*c.y + m[1][2]*c.z,
+    m[2][0]*c.x + m[2][1]*c.y + m[2][2]*c.z,
+  )
+}
+
+//ACES filmic, Stephen Hill's fit of the RRT and sRGB ODT.
+fn aces(color: Vector3) -> Vector3 {
+  const INPUT: [[f32; 3]; 3] = [[0.59719, 0.35458, 0.04823], [0.07600, 0.90834, 0.01566], [0.02840, 0.13383, 0.83777]];
+  const OUTPUT: [[f32; 3]; 3] = [[1.60475, -0.53108, -0.07367], [-0.10208, 1.10813, -0.00605], [-0.00327, -0.07276, 1.07602]];
+  let fit = |v: f32| (v*(v + 0.0245786) - 0.000090537)/(v*(0.983729*v + 0.4329510) + 0.238081);
+  let c = mat3(INPUT, color);
+  let c = Vector3::new(fit(c.x), fit(c.y), fit(c.z));
+  return min3(max3(mat3(OUTPUT, c), 0.0), 1.0);
+}
+
+//AgX-like, a log encoding in a slightly desaturated space followed by a sigmoid (after Troy Sobotka's AgX).
+fn agx(color: Vector3) -> Vector3 {
+  const INSET: [[f32; 3]; 3] = [[0.84247905, 0.0784336, 0.079223745], [0.042328242, 0.87846863, 0.07916613], [0.042375654, 0.0784336, 0.879143]];
+  const OUTSET: [[f32; 3]; 3] = [[1.196879, -0.09802088, -0.09902974], [-0.052896854, 1.1519032, -0.098961174], [-0.052971635, -0.09804345, 1.1510737]];
+  const MIN_EV: f32 = -12.47393;
+  const MAX_EV: f32 = 4.026069;
+  let curve = |v: f32| {
+    let v = ((v.max(1e-10).log2()).max(MIN_EV).min(MAX_EV) - MIN_EV)/(MAX_EV - MIN_EV);
+    let v2 = v*v;
+    let v4 = v2*v2;
+    15.5*v4*v2 - 40.14*v4*v + 31.96*v4 - 6.868*v2*v + 0.4298*v2 + 0.1191*v - 0.00232
+  };
+  let c = mat3(INSET, color);
+  let c = mat3(OUTSET, Vector3::new(curve(c.x), curve(c.y), curve(c.z)));
+  //The curve outputs display encoded values, bring them back to linear for the sRGB encoding.
+  let c = max3(c, 0.0);
+  return min3(Vector3::new(c.x.powf(2.2), c.y.powf(2.2), c.z.powf(2.2)), 1.0);
+}
+
+//The sRGB transfer function (linear to encoded).
+pub fn srgb_encode(v: f32) -> f32 {
+  if v <= 0.0031308 {
+    return v*12.92;
+  }
+  return 1.055*v.powf(1.0/2.4) - 0.055;
+}